@@ -77,7 +77,7 @@ impl Sampler for FlawedSampler {
 
 fn main () {
     {
-        let sampler = MySampler::new(42);
+        let _sampler = MySampler::new(42);
         let registry = REGISTRY.lock().unwrap();
     
         assert_eq!(registry.len(), 1);
@@ -86,7 +86,7 @@ fn main () {
     }
 
     {
-        let sampler = FlawedSampler::new(42);
+        let _sampler = FlawedSampler::new(42);
         let registry = REGISTRY.lock().unwrap();
     
         assert_eq!(registry.len(), 2);
@@ -0,0 +1,146 @@
+// Copyright 2025 KenForever1
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! 容量固定的环形队列，类似butil的`BoundedQueue`
+
+use std::collections::VecDeque;
+
+/// 容量固定的环形队列，满了以后新元素会挤掉最旧的元素
+#[derive(Debug, Clone)]
+pub struct BoundedQueue<T> {
+    /// 队列中的元素，队头是最旧的元素
+    items: VecDeque<T>,
+    /// 最多保存的元素数量
+    capacity: usize,
+}
+
+impl<T> BoundedQueue<T> {
+    /// 创建最多保存`capacity`个元素的队列
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "capacity of BoundedQueue must be positive");
+        Self {
+            items: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// 在队尾加入元素，队列已满时返回被挤掉的最旧元素
+    pub fn elim_push(&mut self, item: T) -> Option<T> {
+        let popped = if self.is_full() {
+            self.items.pop_front()
+        } else {
+            None
+        };
+        self.items.push_back(item);
+        popped
+    }
+
+    /// 取出最旧的元素
+    pub fn pop(&mut self) -> Option<T> {
+        self.items.pop_front()
+    }
+
+    /// 获取最旧的元素
+    pub fn bottom(&self) -> Option<&T> {
+        self.items.front()
+    }
+
+    /// 获取倒数第`index`个元素，`top(0)`是最新的元素
+    pub fn top(&self, index: usize) -> Option<&T> {
+        let len = self.items.len();
+        if index < len {
+            self.items.get(len - 1 - index)
+        } else {
+            None
+        }
+    }
+
+    /// 获取第`index`个元素，`get(0)`是最旧的元素
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.items.get(index)
+    }
+
+    /// 从旧到新遍历所有元素
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &T> + ExactSizeIterator {
+        self.items.iter()
+    }
+
+    /// 元素数量
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// 是否为空
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// 是否已满
+    pub fn is_full(&self) -> bool {
+        self.items.len() >= self.capacity
+    }
+
+    /// 最多保存的元素数量
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// 修改容量，缩小时丢弃最旧的元素
+    pub fn set_capacity(&mut self, capacity: usize) {
+        assert!(capacity > 0, "capacity of BoundedQueue must be positive");
+        while self.items.len() > capacity {
+            self.items.pop_front();
+        }
+        self.capacity = capacity;
+    }
+
+    /// 清空队列
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bounded_queue() {
+        let mut queue = BoundedQueue::new(3);
+        assert!(queue.is_empty());
+        assert_eq!(queue.top(0), None);
+
+        for i in 1..=3 {
+            assert_eq!(queue.elim_push(i), None);
+        }
+        assert!(queue.is_full());
+        assert_eq!(queue.elim_push(4), Some(1));
+        assert_eq!(queue.iter().copied().collect::<Vec<_>>(), vec![2, 3, 4]);
+        assert_eq!(queue.bottom(), Some(&2));
+        assert_eq!(queue.top(0), Some(&4));
+        assert_eq!(queue.top(2), Some(&2));
+        assert_eq!(queue.top(3), None);
+        assert_eq!(queue.get(1), Some(&3));
+
+        queue.set_capacity(2);
+        assert_eq!(queue.iter().copied().collect::<Vec<_>>(), vec![3, 4]);
+        queue.set_capacity(4);
+        queue.elim_push(5);
+        assert_eq!(queue.len(), 3);
+
+        assert_eq!(queue.pop(), Some(3));
+        queue.clear();
+        assert!(queue.is_empty());
+    }
+}
@@ -0,0 +1,137 @@
+// Copyright 2025 KenForever1
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! 可替换的时钟，测试中用[`MockClock`]代替真实时间，避免等待
+
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+/// 提供当前时间
+pub trait Clock: Send + Sync + 'static {
+    /// 单调时间，用于计算间隔
+    fn now(&self) -> Instant;
+
+    /// 墙上时间，用于时间戳
+    fn system_time(&self) -> SystemTime;
+
+    /// Unix时间戳（毫秒）
+    fn now_ms(&self) -> u64 {
+        self.system_time()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64
+    }
+}
+
+/// 使用系统时间的时钟
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn system_time(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// 所有默认使用系统时间的地方共享的时钟
+static SYSTEM_CLOCK: Lazy<Arc<dyn Clock>> = Lazy::new(|| Arc::new(SystemClock));
+
+/// 获取系统时钟
+pub fn system_clock() -> Arc<dyn Clock> {
+    SYSTEM_CLOCK.clone()
+}
+
+/// 只有调用`advance`时才前进的时钟
+///
+/// ```ignore
+/// let clock = Arc::new(MockClock::new());
+/// let series = Series::with_clock(AddTo::default(), clock.clone());
+/// clock.advance(Duration::from_secs(1));
+/// ```
+#[derive(Debug)]
+pub struct MockClock {
+    /// 创建时的单调时间
+    start: Instant,
+    /// 创建时的墙上时间
+    start_system_time: SystemTime,
+    /// 已经前进的时间
+    elapsed: Mutex<Duration>,
+}
+
+impl MockClock {
+    /// 创建时钟，墙上时间从一个固定的时间点开始
+    pub fn new() -> Self {
+        Self::starting_at(UNIX_EPOCH + Duration::from_secs(1_700_000_000))
+    }
+
+    /// 从指定的墙上时间开始计时，便于断言时间戳
+    pub fn starting_at(system_time: SystemTime) -> Self {
+        Self {
+            start: Instant::now(),
+            start_system_time: system_time,
+            elapsed: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    /// 前进`duration`
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock() += duration;
+    }
+
+    /// 已经前进的时间
+    pub fn elapsed(&self) -> Duration {
+        *self.elapsed.lock()
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+
+    fn system_time(&self) -> SystemTime {
+        self.start_system_time + self.elapsed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mock_clock() {
+        let clock = MockClock::starting_at(UNIX_EPOCH + Duration::from_secs(10));
+        let start = clock.now();
+        assert_eq!(clock.now(), start);
+        assert_eq!(clock.now_ms(), 10_000);
+
+        clock.advance(Duration::from_millis(1500));
+        assert_eq!(clock.now() - start, Duration::from_millis(1500));
+        assert_eq!(clock.now_ms(), 11_500);
+        assert!(system_clock().now_ms() > 1_700_000_000_000);
+    }
+}
@@ -14,10 +14,15 @@
 
 //! 实现数据的组合器，用于线程本地数据的合并
 
+use std::any::Any;
+use std::cell::RefCell;
 use std::marker::PhantomData;
-use std::cell::UnsafeCell;
-use thread_local::ThreadLocal;
-use parking_lot::Mutex;
+use std::sync::atomic::{
+    AtomicI16, AtomicI32, AtomicI64, AtomicI8, AtomicIsize, AtomicU16, AtomicU32, AtomicU64,
+    AtomicU8, AtomicUsize, Ordering,
+};
+use std::sync::{Arc, Weak};
+use parking_lot::{Mutex, RwLock};
 
 /// 提供标准组合操作，如总和、最大值、最小值和平均值
 pub trait Combiner<T>: Send + Sync + Clone {
@@ -31,129 +36,445 @@ pub trait Combiner<T>: Send + Sync + Clone {
     fn name(&self) -> &str;
 }
 
+/// 组合操作的逆操作，用于从累计的结果中去掉较早的部分
+///
+/// 例如求和的逆操作是减法。最大值、最小值这类操作不可逆，使用[`VoidOp`]。
+///
+/// [`VoidOp`]: crate::reducer::VoidOp
+pub trait InverseOp<T>: Send + Sync + Clone + 'static {
+    /// 是否可逆
+    fn invertible(&self) -> bool {
+        true
+    }
+
+    /// 从`v1`中去掉`v2`的部分
+    fn inverse(&self, v1: T, v2: T) -> T;
+}
+
+/// Agent中保存值的容器，所有操作都只涉及当前容器，不需要全局锁
+pub trait ElementContainer<T>: Send + Sync {
+    /// 创建保存`value`的容器
+    fn new(value: T) -> Self;
+
+    /// 读取当前值
+    fn get(&self) -> T;
+
+    /// 写入新值
+    fn set(&self, value: T);
+
+    /// 写入新值并返回旧值
+    fn exchange(&self, value: T) -> T;
+
+    /// 用`f`原子地更新值，`f`可能被调用多次
+    fn modify<F: Fn(T) -> T>(&self, f: F);
+
+    /// 用修改器原地更新值
+    fn modify_with<V, M: Modifier<T, V>>(&self, modifier: &M, arg: &V) {
+        self.modify(|mut value| {
+            modifier.modify(&mut value, arg);
+            value
+        });
+    }
+}
+
+/// 可以保存在Agent中的值，决定了Agent使用的容器
+///
+/// 整数和浮点数使用原子变量，其它类型可以使用[`LockedElement`]：
+///
+/// ```ignore
+/// impl AgentValue for MyValue {
+///     type Container = LockedElement<MyValue>;
+/// }
+/// ```
+pub trait AgentValue: Clone + Send + Sync + 'static {
+    /// 保存值的容器
+    type Container: ElementContainer<Self>;
+}
+
+macro_rules! impl_atomic_element {
+    ($($t:ty => $atomic:ty),* $(,)?) => {
+        $(
+            impl ElementContainer<$t> for $atomic {
+                fn new(value: $t) -> Self {
+                    <$atomic>::new(value)
+                }
+
+                fn get(&self) -> $t {
+                    self.load(Ordering::Relaxed)
+                }
+
+                fn set(&self, value: $t) {
+                    self.store(value, Ordering::Relaxed)
+                }
+
+                fn exchange(&self, value: $t) -> $t {
+                    self.swap(value, Ordering::Relaxed)
+                }
+
+                fn modify<F: Fn($t) -> $t>(&self, f: F) {
+                    let mut current = self.load(Ordering::Relaxed);
+                    while let Err(actual) = self.compare_exchange_weak(
+                        current,
+                        f(current),
+                        Ordering::Relaxed,
+                        Ordering::Relaxed,
+                    ) {
+                        current = actual;
+                    }
+                }
+            }
+
+            impl AgentValue for $t {
+                type Container = $atomic;
+            }
+        )*
+    };
+}
+
+impl_atomic_element! {
+    i8 => AtomicI8,
+    i16 => AtomicI16,
+    i32 => AtomicI32,
+    i64 => AtomicI64,
+    isize => AtomicIsize,
+    u8 => AtomicU8,
+    u16 => AtomicU16,
+    u32 => AtomicU32,
+    u64 => AtomicU64,
+    usize => AtomicUsize,
+}
+
+macro_rules! impl_atomic_float {
+    ($($t:ty => $name:ident($bits:ty)),* $(,)?) => {
+        $(
+            /// 以位模式保存在原子整数中的浮点数
+            pub struct $name($bits);
+
+            impl ElementContainer<$t> for $name {
+                fn new(value: $t) -> Self {
+                    Self(<$bits>::new(value.to_bits()))
+                }
+
+                fn get(&self) -> $t {
+                    <$t>::from_bits(self.0.load(Ordering::Relaxed))
+                }
+
+                fn set(&self, value: $t) {
+                    self.0.store(value.to_bits(), Ordering::Relaxed)
+                }
+
+                fn exchange(&self, value: $t) -> $t {
+                    <$t>::from_bits(self.0.swap(value.to_bits(), Ordering::Relaxed))
+                }
+
+                fn modify<F: Fn($t) -> $t>(&self, f: F) {
+                    let mut current = self.0.load(Ordering::Relaxed);
+                    while let Err(actual) = self.0.compare_exchange_weak(
+                        current,
+                        f(<$t>::from_bits(current)).to_bits(),
+                        Ordering::Relaxed,
+                        Ordering::Relaxed,
+                    ) {
+                        current = actual;
+                    }
+                }
+            }
+
+            impl AgentValue for $t {
+                type Container = $name;
+            }
+        )*
+    };
+}
+
+impl_atomic_float! {
+    f32 => AtomicF32(AtomicU32),
+    f64 => AtomicF64(AtomicU64),
+}
+
+/// 用锁保护的容器，用于无法放入原子变量的类型
+///
+/// 锁只属于一个线程的Agent，只有读取时才会和写入线程竞争
+pub struct LockedElement<T>(Mutex<T>);
+
+impl<T: Clone + Send + Sync> ElementContainer<T> for LockedElement<T> {
+    fn new(value: T) -> Self {
+        Self(Mutex::new(value))
+    }
+
+    fn get(&self) -> T {
+        self.0.lock().clone()
+    }
+
+    fn set(&self, value: T) {
+        *self.0.lock() = value;
+    }
+
+    fn exchange(&self, value: T) -> T {
+        std::mem::replace(&mut *self.0.lock(), value)
+    }
+
+    fn modify<F: Fn(T) -> T>(&self, f: F) {
+        let mut guard = self.0.lock();
+        *guard = f(guard.clone());
+    }
+
+    /// 在锁内原地修改，不需要复制值
+    fn modify_with<V, M: Modifier<T, V>>(&self, modifier: &M, arg: &V) {
+        modifier.modify(&mut self.0.lock(), arg);
+    }
+}
 
 /// 一个线程本地的Agent
-pub struct Agent<T> {
+pub struct Agent<T: AgentValue> {
     /// 存储的值
-    pub value: T,
+    pub element: T::Container,
     /// Agent的ID
     pub id: u64,
 }
 
-/// 用于帮助组合多个线程的数据
-pub struct AgentCombiner<T, Op> 
+/// 组合器在所有线程之间共享的状态
+struct SharedState<T: AgentValue, Op> {
+    /// 默认值
+    identity: T,
+    /// 组合操作，以修改器的形式原地合并值
+    modifier: OpAsModifier<T, Op>,
+    /// 所有存活线程的Agent
+    agents: Mutex<Vec<Arc<Agent<T>>>>,
+    /// 已退出线程提交的值
+    global_result: Mutex<T>,
+}
+
+impl<T, Op> SharedState<T, Op>
 where
-    T: Send + Sync,
+    T: AgentValue,
     Op: Combiner<T> + Send + Sync + 'static + Clone,
 {
-    /// 线程本地存储
-    tls: ThreadLocal<Mutex<Agent<T>>>,
-    /// 默认值
-    identity: T,
-    /// 组合操作
-    op: Op,
-    /// 下一个Agent的ID
-    next_id: u64,
-    /// 变量名称
-    name: UnsafeCell<String>,
-} 
+    /// 把Agent的值合并到全局结果中，并释放这个Agent
+    fn commit_and_erase(&self, agent: &Arc<Agent<T>>) {
+        let mut agents = self.agents.lock();
+        let value = agent.element.exchange(self.identity.clone());
+        self.modifier.modify(&mut self.global_result.lock(), &value);
+        agents.retain(|other| !Arc::ptr_eq(other, agent));
+    }
+}
+
+/// 线程退出时需要提交的Agent
+trait ThreadAgent {
+    /// 把值提交到所属的组合器，组合器已经销毁时什么也不做
+    fn commit_and_erase(&self);
+
+    /// 用于转换回具体的类型
+    fn as_any(&self) -> &dyn Any;
+}
+
+/// 当前线程在某个组合器中的Agent
+struct AgentSlot<T: AgentValue, Op> {
+    agent: Arc<Agent<T>>,
+    state: Weak<SharedState<T, Op>>,
+}
 
-unsafe impl<T, Op> Send for AgentCombiner<T, Op> 
+impl<T, Op> ThreadAgent for AgentSlot<T, Op>
 where
-    T: Send + Sync,
-    Op:  Combiner<T> + Send + Sync + 'static + Clone,
-{}
-unsafe impl<T, Op> Sync for AgentCombiner<T, Op> 
+    T: AgentValue,
+    Op: Combiner<T> + Send + Sync + 'static + Clone,
+{
+    fn commit_and_erase(&self) {
+        if let Some(state) = self.state.upgrade() {
+            state.commit_and_erase(&self.agent);
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// 当前线程在所有组合器中的Agent，以组合器的ID为下标
+#[derive(Default)]
+struct ThreadAgents {
+    slots: Vec<Option<Box<dyn ThreadAgent>>>,
+}
+
+impl Drop for ThreadAgents {
+    /// 线程退出时把所有Agent提交到各自的组合器
+    fn drop(&mut self) {
+        for slot in self.slots.drain(..).flatten() {
+            slot.commit_and_erase();
+        }
+    }
+}
+
+thread_local! {
+    static THREAD_AGENTS: RefCell<ThreadAgents> = RefCell::new(ThreadAgents::default());
+}
+
+/// 下一个未使用过的组合器ID
+static NEXT_COMBINER_ID: AtomicUsize = AtomicUsize::new(0);
+/// 已销毁的组合器归还的ID，复用它们使线程本地的数组保持紧凑
+static FREE_COMBINER_IDS: Mutex<Vec<usize>> = parking_lot::const_mutex(Vec::new());
+
+/// 用于帮助组合多个线程的数据
+///
+/// 每个线程只修改自己的Agent。线程退出时它的Agent被合并到全局结果中并释放，
+/// 线程池伸缩时总数保持正确，内存也不会随着线程的创建不断增长。
+pub struct AgentCombiner<T, Op>
 where
-    T: Send + Sync,
-    Op:  Combiner<T> + Send + Sync + 'static + Clone,
-{}
+    T: AgentValue,
+    Op: Combiner<T> + Send + Sync + 'static + Clone,
+{
+    /// 组合器的ID，也是Agent在线程本地数组中的下标
+    id: usize,
+    /// 所有线程共享的状态
+    state: Arc<SharedState<T, Op>>,
+    /// 下一个Agent的ID
+    next_id: AtomicU64,
+    /// 变量名称
+    name: RwLock<String>,
+}
 
 impl<T, Op> AgentCombiner<T, Op>
 where
-    T: Clone + Send + Sync + 'static,
+    T: AgentValue,
     Op: Combiner<T> + Send + Sync + 'static + Clone,
 {
     /// 创建新的组合器
     pub fn new(identity: T, op: Op, name: String) -> Self {
+        let id = FREE_COMBINER_IDS
+            .lock()
+            .pop()
+            .unwrap_or_else(|| NEXT_COMBINER_ID.fetch_add(1, Ordering::Relaxed));
         Self {
-            tls: ThreadLocal::new(),
-            identity,
-            op,
-            next_id: 1,
-            name: UnsafeCell::new(name),
+            id,
+            state: Arc::new(SharedState {
+                global_result: Mutex::new(identity.clone()),
+                identity,
+                modifier: OpAsModifier::new(op),
+                agents: Mutex::new(Vec::new()),
+            }),
+            next_id: AtomicU64::new(1),
+            name: RwLock::new(name),
         }
     }
-    
-    /// 获取或创建当前线程的Agent
-    pub fn get_or_create_tls_agent(&mut self) -> Option<&Mutex<Agent<T>>> {
-        Some(self.tls.get_or(|| {
-            let id = self.next_id;
-            self.next_id += 1;
-            
-            Mutex::new(Agent {
-                value: self.identity.clone(),
-                id,
+
+    /// 获取或创建当前线程的Agent，线程本地存储已经销毁时返回`None`
+    pub fn get_or_create_tls_agent(&self) -> Option<Arc<Agent<T>>> {
+        THREAD_AGENTS
+            .try_with(|agents| {
+                if let Some(agent) = self.find_agent(&agents.borrow()) {
+                    return agent;
+                }
+
+                let agent = Arc::new(Agent {
+                    element: T::Container::new(self.state.identity.clone()),
+                    id: self.next_id.fetch_add(1, Ordering::Relaxed),
+                });
+                self.state.agents.lock().push(agent.clone());
+
+                let slot = Box::new(AgentSlot {
+                    agent: agent.clone(),
+                    state: Arc::downgrade(&self.state),
+                });
+                let mut agents = agents.borrow_mut();
+                if agents.slots.len() <= self.id {
+                    agents.slots.resize_with(self.id + 1, || None);
+                }
+                // 旧的Agent属于一个已经销毁的组合器，直接丢弃
+                agents.slots[self.id] = Some(slot);
+                agent
             })
-        }))
+            .ok()
     }
-    
-    /// 对所有Agent的值执行组合操作
+
+    /// 在线程本地的数组中查找属于这个组合器的Agent
+    fn find_agent(&self, agents: &ThreadAgents) -> Option<Arc<Agent<T>>> {
+        let slot = agents.slots.get(self.id)?.as_ref()?;
+        let slot = slot.as_any().downcast_ref::<AgentSlot<T, Op>>()?;
+        // 同一个ID可能属于之前已经销毁的组合器
+        if slot.state.as_ptr() == Arc::as_ptr(&self.state) {
+            Some(slot.agent.clone())
+        } else {
+            None
+        }
+    }
+
+    /// 把`value`合并到当前线程的Agent中，不涉及其它线程
+    pub fn add(&self, value: T) {
+        self.add_with(&self.state.modifier, &value);
+    }
+
+    /// 用`modifier`修改当前线程的Agent，用于不以`T`为参数的更新
+    pub fn add_with<V, M: Modifier<T, V>>(&self, modifier: &M, arg: &V) {
+        match self.get_or_create_tls_agent() {
+            Some(agent) => agent.element.modify_with(modifier, arg),
+            // 线程正在退出，直接合并到全局结果中
+            None => modifier.modify(&mut self.state.global_result.lock(), arg),
+        }
+    }
+
+    /// 对全局结果和所有Agent的值执行组合操作
     pub fn combine_agents(&self) -> T {
-        let result = self.identity.clone();
-        
-        for agent in self.tls.iter() {
-            let agent_value = agent.lock().value.clone();
-            self.op.combine(result.clone(), agent_value);
+        let agents = self.state.agents.lock();
+        let mut result = self.state.global_result.lock().clone();
+
+        for agent in agents.iter() {
+            self.state.modifier.modify(&mut result, &agent.element.get());
         }
-        
+
         result
     }
-    
-    /// 重置所有Agent的值，并返回组合前的值
+
+    /// 重置全局结果和所有Agent的值，并返回组合前的值
+    ///
+    /// 每个Agent的值被原子地换成identity，重置期间的写入不会丢失
     pub fn reset_all_agents(&self) -> T {
-        let result = self.combine_agents();
-        
-        for agent in self.tls.iter() {
-            let mut guard = agent.lock();
-            guard.value = self.identity.clone();
+        let agents = self.state.agents.lock();
+        let mut result = std::mem::replace(
+            &mut *self.state.global_result.lock(),
+            self.state.identity.clone(),
+        );
+
+        for agent in agents.iter() {
+            let value = agent.element.exchange(self.state.identity.clone());
+            self.state.modifier.modify(&mut result, &value);
         }
-        
+
         result
     }
-    
-    /// 获取线程本地存储迭代器
-    pub fn iter(&self) -> thread_local::Iter<Mutex<Agent<T>>> {
-        self.tls.iter()
+
+    /// 获取所有存活线程的Agent
+    pub fn agents(&self) -> Vec<Arc<Agent<T>>> {
+        self.state.agents.lock().clone()
     }
-    
-    /// 获取所有线程的Agent数量
+
+    /// 获取所有存活线程的Agent数量
     pub fn agent_count(&self) -> usize {
-        let mut count = 0;
-        for _ in self.tls.iter() {
-            count += 1;
-        }
-        count
+        self.state.agents.lock().len()
     }
-    
+
     /// 获取组合操作
     pub fn op(&self) -> &Op {
-        &self.op
+        &self.state.modifier.0
     }
 
-        
     /// 设置变量名称
     pub fn set_name(&self, name: String) {
-        unsafe {
-            *self.name.get() = name;
-        }
+        *self.name.write() = name;
     }
-    
+
     /// 获取变量名称
-    pub fn name(&self) -> &str {
-        unsafe {
-            &*self.name.get()
-        }
+    pub fn name(&self) -> String {
+        self.name.read().clone()
+    }
+}
+
+impl<T, Op> Drop for AgentCombiner<T, Op>
+where
+    T: AgentValue,
+    Op: Combiner<T> + Send + Sync + 'static + Clone,
+{
+    fn drop(&mut self) {
+        FREE_COMBINER_IDS.lock().push(self.id);
     }
 }
 
@@ -250,4 +571,61 @@ impl SampleErrorHandler for LoggingErrorHandler {
     fn on_error(&self, error: &str) {
         log::error!("Sampler error: {}", error);
     }
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reducer::SumCombiner;
+
+    #[test]
+    fn test_element_containers() {
+        let int = <AtomicI64 as ElementContainer<i64>>::new(5);
+        int.modify(|v| v * 3);
+        assert_eq!(int.exchange(1), 15);
+        assert_eq!(ElementContainer::<i64>::get(&int), 1);
+
+        let float = AtomicF64::new(0.5);
+        float.modify(|v| v + 0.25);
+        assert_eq!(float.get(), 0.75);
+        float.set(-1.0);
+        assert_eq!(float.exchange(2.0), -1.0);
+
+        let locked = LockedElement::new(String::from("a"));
+        locked.modify(|v| v + "b");
+        assert_eq!(locked.get(), "ab");
+    }
+
+    #[test]
+    fn test_commit_and_erase_on_thread_exit() {
+        let combiner = Arc::new(AgentCombiner::new(0i64, SumCombiner, String::new()));
+        for _ in 0..8 {
+            let threads: Vec<_> = (0..8)
+                .map(|_| {
+                    let combiner = combiner.clone();
+                    std::thread::spawn(move || {
+                        for _ in 0..1000 {
+                            combiner.add(1);
+                        }
+                    })
+                })
+                .collect();
+            for thread in threads {
+                thread.join().unwrap();
+            }
+        }
+        assert_eq!(combiner.combine_agents(), 64 * 1000);
+        assert_eq!(combiner.agent_count(), 0);
+        assert_eq!(combiner.reset_all_agents(), 64 * 1000);
+        assert_eq!(combiner.combine_agents(), 0);
+
+        // 销毁的组合器的ID被复用时，不会用到之前的Agent
+        let old = AgentCombiner::new(0i64, SumCombiner, String::new());
+        old.add(5);
+        drop(old);
+        let new = AgentCombiner::new(0.0f64, SumCombiner, String::new());
+        new.add(1.5);
+        assert_eq!(new.combine_agents(), 1.5);
+        assert_eq!(new.agent_count(), 1);
+    }
+}
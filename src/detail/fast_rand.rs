@@ -0,0 +1,72 @@
+// Copyright 2025 KenForever1
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! 线程本地的快速随机数，类似butil的`fast_rand`，不能用于加密
+
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+thread_local! {
+    /// xorshift64*的状态，不能为0
+    static STATE: Cell<u64> = Cell::new(seed());
+}
+
+/// 用标准库的随机哈希种子初始化每个线程的状态
+fn seed() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_usize(&hasher as *const _ as usize);
+    hasher.finish() | 1
+}
+
+/// 返回一个随机的u64
+pub fn fast_rand() -> u64 {
+    STATE
+        .try_with(|state| {
+            let mut x = state.get();
+            x ^= x >> 12;
+            x ^= x << 25;
+            x ^= x >> 27;
+            state.set(x);
+            x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+        })
+        .unwrap_or_else(|_| seed())
+}
+
+/// 返回`[0, range)`之间的随机数，`range`为0时返回0
+pub fn fast_rand_less_than(range: u64) -> u64 {
+    if range == 0 {
+        return 0;
+    }
+    // 用乘法代替取模，避免取模带来的偏差和开销
+    ((fast_rand() as u128 * range as u128) >> 64) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fast_rand_less_than() {
+        let mut counts = [0; 4];
+        for _ in 0..4000 {
+            let value = fast_rand_less_than(4);
+            assert!(value < 4);
+            counts[value as usize] += 1;
+        }
+        assert!(counts.iter().all(|&count| count > 800), "{:?}", counts);
+        assert_eq!(fast_rand_less_than(0), 0);
+        assert_ne!(fast_rand(), fast_rand());
+    }
+}
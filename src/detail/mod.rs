@@ -1,3 +1,7 @@
 pub mod combiner;
 pub mod series;
-pub mod sampler;
\ No newline at end of file
+pub mod sampler;
+pub mod wildcard;
+pub mod bounded_queue;
+pub mod fast_rand;
+pub mod clock;
@@ -14,15 +14,21 @@
 
 //! 实现对变量进行定期采样的功能
 
+use std::any::Any;
 use std::fmt;
 use std::sync::{Arc, Weak};
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::thread;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread::{self, JoinHandle};
 use std::time::{Duration, Instant};
-use parking_lot::Mutex;
-use once_cell::sync::Lazy;
+use parking_lot::{Condvar, Mutex, MutexGuard};
+use once_cell::sync::{Lazy, OnceCell};
 
-use crate::detail::combiner::Combiner;
+use crate::detail::bounded_queue::BoundedQueue;
+use crate::detail::clock::{system_clock, Clock};
+use crate::detail::combiner::{Combiner, InverseOp};
+use crate::detail::series::Series;
+use crate::status::PassiveStatus;
+use crate::variable::{SeriesOptions, Variable};
 use crate::window::SERIES_IN_SECOND;
 use super::combiner::SampleErrorHandler;
 use crate::reducer::ReducerTrait;
@@ -30,12 +36,27 @@ use crate::reducer::ReducerTrait;
 pub static GLOBAL_SAMPLER_STATE: Lazy<Arc<Mutex<GlobalSamplerState>>> = Lazy::new(|| {
     Arc::new(Mutex::new(GlobalSamplerState {
         samplers: Vec::new(),
-        last_sample_time: Instant::now(),
-        sample_interval: Duration::from_secs(1),
+        clock: system_clock(),
+        manual: false,
         is_running: false,
+        stopped: false,
+        handle: None,
     }))
 });
 
+/// 有新的采样器注册或者需要停止时唤醒采样线程
+static SAMPLER_WAKEUP: Condvar = Condvar::new();
+
+/// 最近一次采样花费的时间（微秒）
+static TICK_COST_US: AtomicU64 = AtomicU64::new(0);
+
+/// 暴露采样线程最近一次采样花费的时间，采样线程第一次启动时暴露
+static TICK_COST_VAR: Lazy<PassiveStatus<u64>> = Lazy::new(|| {
+    let status = PassiveStatus::new(|| TICK_COST_US.load(Ordering::Relaxed));
+    let _ = status.expose("bvar_sampler_tick_cost_us");
+    status
+});
+
 /// 采样器特性
 pub trait Sampler: Send + Sync + 'static {
     /// 获取采样间隔
@@ -51,130 +72,286 @@ pub trait Sampler: Send + Sync + 'static {
     fn destroy(&self);
 }
 
+/// 注册的采样器以及下一次采样的时间
+struct ScheduledSampler {
+    /// 采样器，由使用它的变量持有
+    sampler: Weak<dyn Sampler>,
+    /// 下一次采样的时间
+    next_time: Instant,
+}
+
 /// 全局采样器状态
+///
+/// 后台采样线程按照每个采样器自己的[`Sampler::interval`]调用`take_sample`，
+/// 没有到期的采样器时一直睡眠到最早的采样时间。所有采样器都被释放后线程退出，
+/// 有新的采样器注册时再次启动。
+///
+/// 测试中可以用[`GlobalSamplerState::new_manual`]创建不启动线程的调度器，
+/// 配合[`MockClock`]调用[`GlobalSamplerState::tick`]手动驱动采样。
+///
+/// [`MockClock`]: crate::detail::clock::MockClock
 pub struct GlobalSamplerState {
     /// 所有注册的采样器
-    samplers: Vec<Weak<dyn Sampler>>,
-    /// 上次采样时间
-    last_sample_time: Instant,
-    /// 采样间隔
-    sample_interval: Duration,
+    samplers: Vec<ScheduledSampler>,
+    /// 计算采样时间的时钟
+    clock: Arc<dyn Clock>,
+    /// 是否由调用者手动驱动，不启动采样线程
+    manual: bool,
     /// 采样线程是否运行中
     is_running: bool,
+    /// 是否被[`stop_sampler`]停止，停止后注册采样器不会启动线程
+    stopped: bool,
+    /// 采样线程
+    handle: Option<JoinHandle<()>>,
 }
 
 impl GlobalSamplerState {
-    /// 注册一个新的采样器
-    pub fn register_sampler(&mut self, sampler: Weak<dyn Sampler>) {
-        // 清理已失效的采样器
-        self.samplers.retain(|s| s.upgrade().is_some());
-        
-        // 添加新采样器
-        self.samplers.push(sampler);
+    /// 创建由调用者手动驱动的调度器，用`clock`计算采样时间
+    pub fn new_manual(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            samplers: Vec::new(),
+            clock,
+            manual: true,
+            is_running: false,
+            stopped: false,
+            handle: None,
+        }
+    }
 
-        self.samplers.iter().for_each(|s| {
-            println!("call GlobalSamplerState::register_sampler s.upgrade().is_some(): {}", s.upgrade().is_some());
+    /// 注册一个新的采样器，一个间隔后第一次采样
+    pub fn register_sampler(&mut self, sampler: Weak<dyn Sampler>) {
+        let Some(interval) = sampler.upgrade().map(|sampler| sampler.interval()) else {
+            return;
+        };
+        self.samplers.push(ScheduledSampler {
+            sampler,
+            next_time: self.clock.now() + interval,
         });
-        
-        // 如果还没有启动线程，则启动
-        if !self.is_running {
+
+        if self.manual {
+            return;
+        }
+        if self.is_running {
+            SAMPLER_WAKEUP.notify_all();
+        } else if !self.stopped {
             self.start_sampler_thread();
         }
     }
-    
+
+    /// 在当前线程中执行所有到期的采样，返回执行的采样器数量，停止后不采样
+    pub fn tick(&mut self) -> usize {
+        if self.stopped {
+            return 0;
+        }
+        let due = self.take_due_samplers(self.clock.now());
+        for sampler in &due {
+            sampler.take_sample();
+        }
+        due.len()
+    }
+
+    /// 最早的下一次采样时间，没有采样器时返回`None`
+    pub fn next_sample_time(&self) -> Option<Instant> {
+        self.samplers.iter().map(|scheduled| scheduled.next_time).min()
+    }
+
+    /// 注册的采样器数量，包括还没有被清理的已释放的采样器
+    pub fn sampler_count(&self) -> usize {
+        self.samplers.len()
+    }
+
+    /// 采样线程是否运行中
+    pub fn is_running(&self) -> bool {
+        self.is_running
+    }
+
+    /// 停止采样，已注册的采样器被保留，返回需要等待退出的采样线程
+    pub fn stop(&mut self) -> Option<JoinHandle<()>> {
+        self.stopped = true;
+        self.handle.take()
+    }
+
+    /// 恢复被[`GlobalSamplerState::stop`]停止的采样
+    pub fn restart(&mut self) {
+        self.stopped = false;
+        if !self.manual && !self.samplers.is_empty() {
+            self.start_sampler_thread();
+        }
+    }
+
     /// 启动采样线程
     fn start_sampler_thread(&mut self) {
-        println!("call GlobalSamplerState::start_sampler_thread");
         if self.is_running {
             return;
         }
-        println!("call GlobalSamplerState::start_sampler_thread is_running: {}", self.is_running);
-        
-        self.is_running = true;
-        
-        // 克隆一份状态用于线程
         let state = GLOBAL_SAMPLER_STATE.clone();
-        
-        // 启动后台线程
-        thread::spawn(move || {
-            println!("call GlobalSamplerState::start_sampler_thread thread::spawn");
-            loop {
-                // 睡眠一段时间
-                thread::sleep(Duration::from_millis(100));
-                
-                // 检查是否需要采样
-                let mut guard = state.lock();
-
-                guard.samplers.iter().for_each(|s| {
-                    println!("call GlobalSamplerState::start_sampler_thread s.upgrade().is_some(): {}", s.upgrade().is_some());
-                });
+        match thread::Builder::new()
+            .name("bvar_sampler".to_string())
+            .spawn(move || run_sampler_thread(state))
+        {
+            Ok(handle) => {
+                self.is_running = true;
+                self.handle = Some(handle);
+                log::debug!("sampler thread started");
+            }
+            Err(err) => log::error!("failed to start sampler thread: {}", err),
+        }
+    }
 
-                let now = Instant::now();
-                
-                println!("call GlobalSamplerState::start_sampler_thread now");
-                if now.duration_since(guard.last_sample_time) >= guard.sample_interval {
-                    println!("call GlobalSamplerState::start_sampler_thread now.duration_since(guard.last_sample_time) >= guard.sample_interval");
-                    guard.last_sample_time = now;
-                    
-
-                    println!("len of samplers: {}", guard.samplers.len());
-                    // 获取所有有效的采样器
-                    let valid_samplers: Vec<_> = guard.samplers
-                        .iter()
-                        .filter_map(|s| {
-                            // if s.upgrade().is_none() {
-                            //     println!("call GlobalSamplerState::start_sampler_thread s.upgrade().is_none()");
-                            //     return None;
-                            // }
-                            s.upgrade()
-                        }    
-                        )
-                        .collect();
-                    
-                    println!("call GlobalSamplerState::start_sampler_thread valid_samplers: {}", valid_samplers.len());
-                    // 释放锁，避免在采样期间持有锁
-                    drop(guard);
-                    
-                    // 对每个采样器执行采样
-                    for sampler in valid_samplers {
-                        println!("call GlobalSamplerState::start_sampler_thread for sampler");
-                        sampler.take_sample();
-                    }
-                    
-                    // 清理无效的采样器
-                    let mut guard = state.lock();
-                    guard.samplers.retain(|s| s.upgrade().is_some());
-                    
-                    // 如果没有采样器了，退出线程
-                    if guard.samplers.is_empty() {
-                        guard.is_running = false;
-                        break;
-                    }
-                }
+    /// 取出到期的采样器并安排下一次采样，同时清理已释放的采样器
+    fn take_due_samplers(&mut self, now: Instant) -> Vec<Arc<dyn Sampler>> {
+        let mut due = Vec::new();
+        self.samplers.retain_mut(|scheduled| {
+            if scheduled.sampler.strong_count() == 0 {
+                return false;
+            }
+            if scheduled.next_time > now {
+                return true;
+            }
+            let Some(sampler) = scheduled.sampler.upgrade() else {
+                return false;
+            };
+            // 落后超过一个间隔时不补采，从现在开始重新计时
+            let interval = sampler.interval();
+            scheduled.next_time += interval;
+            if scheduled.next_time <= now {
+                scheduled.next_time = now + interval;
             }
+            due.push(sampler);
+            true
         });
+        due
+    }
+}
+
+/// 采样线程的主循环
+fn run_sampler_thread(state: Arc<Mutex<GlobalSamplerState>>) {
+    Lazy::force(&TICK_COST_VAR);
+    let mut guard = state.lock();
+    loop {
+        if guard.stopped {
+            break;
+        }
+        let now = guard.clock.now();
+        let due = guard.take_due_samplers(now);
+        if guard.samplers.is_empty() {
+            break;
+        }
+        if !due.is_empty() {
+            // 采样期间不持有锁，采样器可以注册新的采样器
+            MutexGuard::unlocked(&mut guard, || {
+                let start = Instant::now();
+                for sampler in &due {
+                    sampler.take_sample();
+                }
+                TICK_COST_US.store(start.elapsed().as_micros() as u64, Ordering::Relaxed);
+                drop(due);
+            });
+            continue;
+        }
+        if let Some(next_time) = guard.next_sample_time() {
+            SAMPLER_WAKEUP.wait_until(&mut guard, next_time);
+        }
     }
+    guard.is_running = false;
+    log::debug!("sampler thread exited");
+}
+
+/// 停止采样线程并等待它退出，已注册的采样器被保留，直到[`restart_sampler`]
+pub fn stop_sampler() {
+    let handle = GLOBAL_SAMPLER_STATE.lock().stop();
+    SAMPLER_WAKEUP.notify_all();
+    if let Some(handle) = handle {
+        if handle.thread().id() != thread::current().id() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// 重新启动被[`stop_sampler`]停止的采样线程
+pub fn restart_sampler() {
+    GLOBAL_SAMPLER_STATE.lock().restart();
+}
+
+/// 最近一次采样花费的时间
+pub fn sampler_tick_cost() -> Duration {
+    Duration::from_micros(TICK_COST_US.load(Ordering::Relaxed))
 }
 
+/// 采样得到的一个样本
+#[derive(Debug, Clone)]
+pub struct Sample<T> {
+    /// 样本数据
+    pub value: T,
+    /// 采样时间
+    pub time: Instant,
+}
+
+/// 数据源上共享的采样器
+///
+/// 同一个数据源上的多个窗口应该使用同一个采样器，否则不可逆的操作会被重复重置。
+/// 数据源只保存采样器的弱引用，采样器由使用它的窗口持有。
+#[derive(Clone, Default)]
+pub struct SharedSampler(Arc<Mutex<Option<Weak<dyn Any + Send + Sync>>>>);
+
+impl SharedSampler {
+    /// 创建空的共享采样器
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 获取已经存在的采样器，不存在时用`make`创建
+    pub fn get_or_create<S, F>(&self, make: F) -> Arc<S>
+    where
+        S: Send + Sync + 'static,
+        F: FnOnce() -> Arc<S>,
+    {
+        let mut slot = self.0.lock();
+        let existing = slot
+            .as_ref()
+            .and_then(Weak::upgrade)
+            .and_then(|sampler| sampler.downcast::<S>().ok());
+        if let Some(sampler) = existing {
+            return sampler;
+        }
+
+        let sampler = make();
+        let weak: Weak<dyn Any + Send + Sync> = Arc::downgrade(&sampler) as Weak<S>;
+        *slot = Some(weak);
+        sampler
+    }
+}
+
+impl fmt::Debug for SharedSampler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SharedSampler")
+    }
+}
 
-/// 采样器
+/// 定期记录Reducer的值的采样器
+///
+/// `InvOp`可逆时每次记录Reducer的累计值，窗口内的值为`最新的样本 InvOp 窗口起点的样本`；
+/// 不可逆时每次采样都会重置Reducer，窗口内的值为窗口内所有样本用`Op`组合的结果。
 pub struct ReducerSampler<Owner, T, Op, InvOp> where
 T: Clone + Send + Sync,
 Op: Clone + Combiner<T> + Send + Sync + 'static,
-InvOp: Clone + Send + Sync + 'static,
+InvOp: InverseOp<T>,
 Owner: Clone + Send + Sync + 'static + ReducerTrait<T, Op>,
 {
-    /// 变量所有者
-    owner: Weak<Owner>,
+    /// 被采样的Reducer
+    owner: Owner,
     /// 是否已经销毁
     destroyed: AtomicBool,
     /// 使用的操作
     op: Op,
     /// 反向操作
     inv_op: InvOp,
+    /// 最近的样本
+    samples: Mutex<BoundedQueue<Sample<T>>>,
     /// 错误处理
     error_handler: Arc<dyn SampleErrorHandler>,
-    _marker: std::marker::PhantomData<T>,
+    /// 采样时间使用的时钟
+    clock: Arc<dyn Clock>,
     weak_self: Mutex<Option<Weak<dyn Sampler + 'static + Send + Sync>>>,
 }
 
@@ -185,35 +362,25 @@ where
     Owner: Clone + Send + Sync + 'static + ReducerTrait<T, Op>,
     T: Clone + Send + Sync + 'static,
     Op: Clone + Combiner<T> + Send + Sync + 'static,
-    InvOp: Clone + Send + Sync + 'static,
+    InvOp: InverseOp<T>,
 {
-    /// 创建新的采样器
-    pub fn new(owner: &Arc<Owner>, op: Op, inv_op: InvOp) -> Arc<Self> {
-
-        // this is error
-        // let self_arc = Arc::new(Self {
-        //     owner: Arc::downgrade(owner),
-        //     destroyed: AtomicBool::new(false),
-        //     op,
-        //     inv_op,
-        //     error_handler: Arc::new(super::combiner::LoggingErrorHandler),
-        //     _marker: std::marker::PhantomData,
-        //     weak_self: Arc::new(Mutex::new(None)),
-        // });
-        // // 生成 Weak 指针并存入结构体
-        // let weak = Arc::downgrade(&self_arc);
-        // self_arc.weak_self.lock().unwrap() = weak;
-        // self_arc
+    /// 创建新的采样器，默认保存最近60秒的样本
+    pub fn new(owner: &Owner, op: Op, inv_op: InvOp) -> Arc<Self> {
+        Self::with_clock(owner, op, inv_op, system_clock())
+    }
 
+    /// 创建用`clock`记录采样时间的采样器
+    pub fn with_clock(owner: &Owner, op: Op, inv_op: InvOp, clock: Arc<dyn Clock>) -> Arc<Self> {
         // 通过 new_cyclic 捕获 weak 指针
         Arc::new_cyclic(|weak| -> Self {
             Self {
-                owner: Arc::downgrade(owner),
+                owner: owner.clone(),
                 destroyed: AtomicBool::new(false),
                 op,
                 inv_op,
+                samples: Mutex::new(BoundedQueue::new(SERIES_IN_SECOND + 1)),
                 error_handler: Arc::new(LoggingErrorHandler),
-                _marker: std::marker::PhantomData,
+                clock,
                 // 立即存入初始化时的 weak 指针
                 weak_self: Mutex::new(Some(weak.clone())),
             }
@@ -234,6 +401,53 @@ where
         false
     }
 
+    /// 保证至少保存最近`window_size`秒的样本
+    pub fn set_window_size(&self, window_size: usize) {
+        let mut samples = self.samples.lock();
+        if samples.capacity() < window_size + 1 {
+            samples.set_capacity(window_size + 1);
+        }
+    }
+
+    /// 以`now`为采样时间记录一个样本
+    pub fn take_sample_at(&self, now: Instant) {
+        if self.destroyed.load(Ordering::Relaxed) {
+            return;
+        }
+        let value = if self.inv_op.invertible() {
+            self.owner.get_value()
+        } else {
+            self.owner.reset()
+        };
+        self.samples.lock().elim_push(Sample { value, time: now });
+    }
+
+    /// 计算最近`window_size`个采样周期内的值，没有样本时返回`None`
+    pub fn get_value(&self, window_size: usize) -> Option<T> {
+        let samples = self.samples.lock();
+        let latest = samples.top(0)?;
+        if self.inv_op.invertible() {
+            let oldest = samples.top(window_size.min(samples.len() - 1))?;
+            Some(self.inv_op.inverse(latest.value.clone(), oldest.value.clone()))
+        } else {
+            let result = samples
+                .iter()
+                .rev()
+                .take(window_size.max(1))
+                .skip(1)
+                .fold(latest.value.clone(), |result, sample| {
+                    self.op.combine(result, sample.value.clone())
+                });
+            Some(result)
+        }
+    }
+
+    /// 获取最近`window_size`个采样周期的样本，从旧到新排列
+    pub fn samples(&self, window_size: usize) -> Vec<Sample<T>> {
+        let samples = self.samples.lock();
+        let skip = samples.len().saturating_sub(window_size + 1);
+        samples.iter().skip(skip).cloned().collect()
+    }
 }
 
 impl<Owner, T, Op, InvOp> Clone for ReducerSampler<Owner, T, Op, InvOp>
@@ -241,7 +455,7 @@ where
     Owner: Clone + Send + Sync + 'static + ReducerTrait<T, Op>,
     T: Clone + Send + Sync + 'static,
     Op: Combiner<T> + Clone + Send + Sync + 'static,
-    InvOp: Clone + Send + Sync + 'static,
+    InvOp: InverseOp<T>,
 {
     fn clone(&self) -> Self {
         Self {
@@ -249,13 +463,13 @@ where
             destroyed: AtomicBool::new(self.destroyed.load(Ordering::Relaxed)),
             op: self.op.clone(),
             inv_op: self.inv_op.clone(),
+            samples: Mutex::new(self.samples.lock().clone()),
             error_handler: self.error_handler.clone(),
-            _marker: std::marker::PhantomData,
+            clock: self.clock.clone(),
             // 特殊处理 weak_self：克隆内部的 Weak 指针
             weak_self: Mutex::new(
                 self.weak_self
                     .lock()
-                    // .unwrap()
                     .as_ref()
                     .map(|w| w.clone())
             ),
@@ -268,34 +482,24 @@ where
     Owner: Clone + Send + Sync + 'static + ReducerTrait<T, Op>,
     T: Clone + Send + Sync + 'static,
     Op: Clone + Combiner<T> + Send + Sync + 'static,
-    InvOp: Clone + Send + Sync + 'static,
+    InvOp: InverseOp<T>,
 {
     fn interval(&self) -> Duration {
         Duration::from_secs(1)
     }
     
     fn take_sample(&self) {
-        if self.destroyed.load(Ordering::Relaxed) {
-            return;
-        }
-        
-        // 从owner中获取值并记录
-        if let Some(owner) = self.owner.upgrade() {
-            // 获取当前值
-            let value = owner.get_value();
-
-            println!("call ReducerSampler::take_sample");
-            
-            // 使用op进行组合
-            let _ = self.op.combine(value.clone(), value);
-            
-            // 记录错误
-            self.error_handler.on_error("采样错误");
+        // 数据源的实现可能会panic，不能让它终止采样线程
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            self.take_sample_at(self.clock.now())
+        }));
+        if result.is_err() {
+            self.error_handler.on_error("panicked while taking a sample");
         }
     }
     
-    fn describe(&self, _f: &mut dyn fmt::Write) {
-        // 实现样本描述
+    fn describe(&self, f: &mut dyn fmt::Write) {
+        let _ = write!(f, "{} samples", self.samples.lock().len());
     }
     
     fn destroy(&self) {
@@ -303,116 +507,342 @@ where
     }
 }
 
-/// 一系列采样数据，用于记录和可视化
+/// 每秒读取一次变量的值并追加到时间序列中的采样器，类似bvar的`SeriesSampler`
 pub struct SeriesSampler<T, Op> {
-    /// 序列数据
-    data: Mutex<Vec<T>>,
-    /// 组合操作
-    op: Op,
+    /// 时间序列
+    series: Series<T, Op>,
+    /// 读取变量的当前值，不能持有变量本身，否则变量和采样器互相引用无法释放
+    getter: Box<dyn Fn() -> T + Send + Sync>,
     /// 是否已销毁
     destroyed: AtomicBool,
 }
 
 impl<T, Op> SeriesSampler<T, Op>
 where
-    T: Clone + Send + Sync + 'static,
+    T: Clone + Default + fmt::Debug + Send + Sync + 'static,
     Op: Combiner<T> + Send + Sync + Clone + 'static,
 {
-    /// 创建新的序列采样器
-    pub fn new(op: Op) -> Self {
+    /// 创建每秒把`getter`的返回值追加到`series`的采样器
+    pub fn new<F>(series: Series<T, Op>, getter: F) -> Self
+    where
+        F: Fn() -> T + Send + Sync + 'static,
+    {
         Self {
-            data: Mutex::new(Vec::with_capacity(SERIES_IN_SECOND)),
-            op,
+            series,
+            getter: Box::new(getter),
             destroyed: AtomicBool::new(false),
         }
     }
-    
-    /// 添加一个样本
-    pub fn append(&self, value: T) {
-        if self.destroyed.load(Ordering::Relaxed) {
-            return;
-        }
-        
-        let mut data = self.data.lock();
-        
-        // 添加新值
-        data.push(value);
-        
-        // 如果超过容量，移除最旧的值
-        if data.len() > SERIES_IN_SECOND {
-            data.remove(0);
-        }
-    }
-    
-    /// 安排采样任务
-    pub fn schedule(&self) -> bool {
-        true
+
+    /// 获取时间序列
+    pub fn series(&self) -> &Series<T, Op> {
+        &self.series
     }
 }
 
 impl<T, Op> Sampler for SeriesSampler<T, Op>
 where
-    T: Clone + Send + Sync + std::fmt::Debug + 'static,
+    T: Clone + Default + fmt::Debug + Send + Sync + 'static,
     Op: Combiner<T> + Send + Sync + Clone + 'static,
 {
     fn interval(&self) -> Duration {
         Duration::from_secs(1)
     }
-    
+
     fn take_sample(&self) {
-        // 序列采样器不需要主动采样
+        if !self.destroyed.load(Ordering::Relaxed) {
+            self.series.append((self.getter)());
+        }
     }
-    
+
     fn describe(&self, f: &mut dyn fmt::Write) {
-        if self.destroyed.load(Ordering::Relaxed) {
-            return;
-        }
-        
-        let data = self.data.lock();
-        
-        // 将数据格式化为JSON数组
-        let _ = write!(f, "[");
-        
-        let mut first = true;
-        for value in data.iter() {
-            if !first {
-                let _ = write!(f, ",");
-            }
-            first = false;
-            
-            // 这里需要根据T类型进行适当格式化
-            let _ = write!(f, "{:?}", value);
-        }
-        
-        let _ = write!(f, "]");
+        self.series.describe(f, &SeriesOptions::default());
     }
-    
+
     fn destroy(&self) {
         self.destroyed.store(true, Ordering::Relaxed);
     }
 }
 
+/// 可以输出历史数据的采样器，用于擦除[`SeriesSampler`]的类型参数
+trait SeriesSource: Send + Sync {
+    /// 立即采样一次
+    fn take_sample(&self);
+
+    /// 以JSON格式输出历史数据，`description`为变量的当前描述
+    fn describe_series(&self, f: &mut dyn fmt::Write, options: &SeriesOptions, description: Option<&str>);
+}
+
+impl<T, Op> SeriesSource for SeriesSampler<T, Op>
+where
+    T: Clone + Default + fmt::Debug + Send + Sync + 'static,
+    Op: Combiner<T> + Send + Sync + Clone + 'static,
+{
+    fn take_sample(&self) {
+        Sampler::take_sample(self);
+    }
+
+    fn describe_series(&self, f: &mut dyn fmt::Write, options: &SeriesOptions, description: Option<&str>) {
+        self.series.describe_with(f, options, description);
+    }
+}
+
+/// 变量上可选的时间序列，所有克隆共享
+///
+/// 默认不记录历史数据，调用[`SharedSeries::enable`]后由全局采样线程每秒采样一次，
+/// 变量的`describe_series`输出最近60秒、60分钟、24小时和30天的数据。
+/// 采样器只被这里持有，所有克隆都释放后全局采样线程自动清理它。
+#[derive(Clone, Default)]
+pub struct SharedSeries(Arc<OnceCell<Arc<dyn SeriesSource>>>);
+
+impl SharedSeries {
+    /// 创建未开启的时间序列
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 开启时间序列并注册到全局采样线程，已经开启时不做任何事
+    pub fn enable<T, Op, F>(&self, make: F)
+    where
+        T: Clone + Default + fmt::Debug + Send + Sync + 'static,
+        Op: Combiner<T> + Send + Sync + Clone + 'static,
+        F: FnOnce() -> SeriesSampler<T, Op>,
+    {
+        let mut created = None;
+        self.0.get_or_init(|| {
+            let sampler = Arc::new(make());
+            created = Some(Arc::downgrade(&sampler));
+            sampler
+        });
+        if let Some(sampler) = created {
+            let weak: Weak<dyn Sampler> = sampler;
+            GLOBAL_SAMPLER_STATE.lock().register_sampler(weak);
+        }
+    }
+
+    /// 是否已经开启
+    pub fn is_enabled(&self) -> bool {
+        self.0.get().is_some()
+    }
+
+    /// 立即采样一次，不等待全局采样线程，未开启时不做任何事
+    pub fn take_sample(&self) {
+        if let Some(sampler) = self.0.get() {
+            sampler.take_sample();
+        }
+    }
+
+    /// 以JSON格式输出`var`的历史数据，未开启时返回false
+    pub fn describe(&self, var: &dyn Variable, f: &mut dyn fmt::Write, options: &SeriesOptions) -> bool {
+        let Some(sampler) = self.0.get() else {
+            return false;
+        };
+        let description = options.include_description.then(|| var.get_description());
+        sampler.describe_series(f, options, description.as_deref());
+        true
+    }
+}
+
+impl fmt::Debug for SharedSeries {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SharedSeries").field("enabled", &self.is_enabled()).finish()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::reducer::AddTo;
-    use crate::reducer::Reducer;
-    use crate::reducer::VoidOp;
+    use crate::detail::clock::MockClock;
+    use crate::reducer::{AddTo, MaxTo, MinusFrom, Reducer, VoidOp};
+    use std::sync::atomic::AtomicUsize;
+
+    type TestSampler = ReducerSampler<Reducer<i32, AddTo<i32>>, i32, AddTo<i32>, VoidOp>;
 
     #[test]
     fn test_sampler() {
-        let sampler : Arc<ReducerSampler<Reducer<i32, AddTo<i32>>, i32, AddTo<_>, VoidOp>> = ReducerSampler::new(
-            &Arc::new(Reducer::new(0, AddTo::default(), "adder".to_string())),
-            AddTo::default(), 
-            VoidOp
-        );
-        sampler.schedule();
+        let clock = Arc::new(MockClock::new());
+        let mut scheduler = GlobalSamplerState::new_manual(clock.clone());
+        let adder = Reducer::new(0, AddTo::default(), "adder".to_string());
+        let sampler: Arc<TestSampler> =
+            ReducerSampler::with_clock(&adder, AddTo::default(), VoidOp, clock.clone());
+        let weak: Weak<dyn Sampler> = Arc::downgrade(&sampler) as Weak<TestSampler>;
+        scheduler.register_sampler(weak);
+
+        // 没有到采样时间
+        adder.add(1);
+        assert_eq!(scheduler.tick(), 0);
+        clock.advance(Duration::from_millis(999));
+        assert_eq!(scheduler.tick(), 0);
+
+        // 每秒采样一次，不可逆的操作每次采样后重置数据源
+        for value in [2, 3, 4] {
+            clock.advance(Duration::from_secs(1));
+            assert_eq!(scheduler.tick(), 1);
+            adder.add(value);
+        }
+        let samples = sampler.samples(10);
+        let values: Vec<i32> = samples.iter().map(|s| s.value).collect();
+        assert_eq!(values, vec![1, 2, 3]);
+        assert_eq!(samples[2].time - samples[0].time, Duration::from_secs(2));
+
+        // 采样器释放后被清理
+        drop(sampler);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(scheduler.tick(), 0);
+        assert_eq!(scheduler.sampler_count(), 0);
+    }
+
+    #[test]
+    fn test_reducer_sampler_window() {
+        let start = Instant::now();
+        let at = |second: u64| start + Duration::from_secs(second);
+
+        // 可逆的操作：窗口内的值为两端样本之差
+        let adder = Reducer::new(0i64, AddTo::default(), "adder".to_string());
+        let sampler = ReducerSampler::new(&adder, AddTo::default(), MinusFrom::default());
+        assert_eq!(sampler.get_value(3), None);
+        for second in 0..5 {
+            adder.add(10 * second as i64);
+            sampler.take_sample_at(at(second));
+        }
+        // 累计值依次为0、10、30、60、100
+        assert_eq!(sampler.get_value(1), Some(40));
+        assert_eq!(sampler.get_value(3), Some(90));
+        assert_eq!(sampler.get_value(100), Some(100));
+        assert_eq!(adder.get_value(), 100);
+        let values: Vec<i64> = sampler.samples(2).iter().map(|s| s.value).collect();
+        assert_eq!(values, vec![30, 60, 100]);
+
+        // 不可逆的操作：每次采样重置数据源，窗口内的值为所有样本的组合
+        let maxer = Reducer::new(i64::MIN, MaxTo::default(), "maxer".to_string());
+        let sampler = ReducerSampler::new(&maxer, MaxTo::default(), VoidOp);
+        for (second, value) in [5, 9, 2, 4].into_iter().enumerate() {
+            maxer.add(value);
+            sampler.take_sample_at(at(second as u64));
+        }
+        assert_eq!(maxer.get_value(), i64::MIN);
+        assert_eq!(sampler.get_value(1), Some(4));
+        assert_eq!(sampler.get_value(2), Some(4));
+        assert_eq!(sampler.get_value(3), Some(9));
+
+        sampler.set_window_size(100);
+        for second in 4..80 {
+            maxer.add(second);
+            sampler.take_sample_at(at(second as u64));
+        }
+        assert_eq!(sampler.samples(100).len(), 80);
+    }
+
+    #[test]
+    fn test_series_sampler() {
+        let clock = Arc::new(MockClock::starting_at(std::time::UNIX_EPOCH));
+        let mut scheduler = GlobalSamplerState::new_manual(clock.clone());
+        let adder = Reducer::new(0i64, AddTo::default(), "adder".to_string());
+        let source = adder.clone();
+        let sampler = Arc::new(SeriesSampler::new(
+            Series::averaged_with_clock(AddTo::default(), clock.clone()),
+            move || source.get_value(),
+        ));
+        let weak: Weak<dyn Sampler> = Arc::downgrade(&sampler) as Weak<SeriesSampler<i64, AddTo<i64>>>;
+        scheduler.register_sampler(weak);
+
+        // 每秒记录一次当前值，满60秒合并为一个分钟级数据点
+        for _ in 0..SERIES_IN_SECOND {
+            adder.add(2);
+            clock.advance(Duration::from_secs(1));
+            assert_eq!(scheduler.tick(), 1);
+        }
+        let seconds = sampler.series().second_points();
+        assert_eq!(seconds.first().map(|p| (p.value, p.timestamp)), Some((2, 1000)));
+        assert_eq!(seconds.last().map(|p| p.value), Some(120));
+        let minutes: Vec<i64> = sampler.series().minute_points().iter().map(|p| p.value).collect();
+        assert_eq!(minutes, vec![61]);
+
+        let shared = SharedSeries::new();
+        let maxer = Reducer::new(0i64, MaxTo::default(), "maxer".to_string());
+        assert!(!shared.describe(&maxer, &mut String::new(), &SeriesOptions::default()));
+        let source = maxer.clone();
+        shared.enable(|| SeriesSampler::new(Series::new(MaxTo::default()), move || source.get_value()));
+        assert!(shared.clone().is_enabled());
+        maxer.add(7);
+        shared.take_sample();
+        let mut buf = String::new();
+        let options = SeriesOptions::new().with_fixed_length(false);
+        assert!(shared.describe(&maxer, &mut buf, &options));
+        assert!(buf.contains("\"description\":\"7\""), "{}", buf);
+        assert!(buf.contains("\"second\":{\"timestamps\":[") && buf.contains("\"values\":[7"), "{}", buf);
+    }
+
+    /// 只记录采样次数的采样器
+    struct CountingSampler {
+        interval: Duration,
+        count: AtomicUsize,
+    }
 
-        thread::sleep(Duration::from_secs(10));
+    impl CountingSampler {
+        fn new(interval: Duration) -> Arc<Self> {
+            Arc::new(Self {
+                interval,
+                count: AtomicUsize::new(0),
+            })
+        }
+
+        fn count(&self) -> usize {
+            self.count.load(Ordering::Relaxed)
+        }
+    }
+
+    impl Sampler for CountingSampler {
+        fn interval(&self) -> Duration {
+            self.interval
+        }
+
+        fn take_sample(&self) {
+            self.count.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn describe(&self, f: &mut dyn fmt::Write) {
+            let _ = write!(f, "{} samples", self.count());
+        }
+
+        fn destroy(&self) {}
+    }
+
+    fn register(scheduler: &mut GlobalSamplerState, sampler: &Arc<CountingSampler>) {
+        let weak: Weak<dyn Sampler> = Arc::downgrade(sampler) as Weak<CountingSampler>;
+        scheduler.register_sampler(weak);
+    }
+
+    #[test]
+    fn test_sampler_scheduler() {
+        let clock = Arc::new(MockClock::new());
+        let mut scheduler = GlobalSamplerState::new_manual(clock.clone());
+        let fast = CountingSampler::new(Duration::from_millis(20));
+        let slow = CountingSampler::new(Duration::from_secs(3600));
+        register(&mut scheduler, &fast);
+        register(&mut scheduler, &slow);
+
+        // 每个采样器按自己的间隔采样
+        for _ in 0..3 {
+            clock.advance(Duration::from_millis(20));
+            assert_eq!(scheduler.tick(), 1);
+        }
+        assert_eq!((fast.count(), slow.count()), (3, 0));
 
-        println!("Sampler finished")
+        // 停止后不再采样，注册新的采样器也不会采样
+        assert!(scheduler.stop().is_none());
+        let late = CountingSampler::new(Duration::from_millis(20));
+        register(&mut scheduler, &late);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(scheduler.tick(), 0);
+        assert_eq!((fast.count(), late.count()), (3, 0));
 
-        // let series: <dyn Sampler>::SeriesSampler<_, Reducer::AddTo<_>> = SeriesSampler::new(AddTo::default());
-        // series.schedule();
+        // 恢复后落后的采样器只补采一次，手动驱动的调度器不会启动线程
+        scheduler.restart();
+        assert!(!scheduler.is_running());
+        assert_eq!(scheduler.tick(), 2);
+        clock.advance(Duration::from_secs(3600));
+        assert_eq!(scheduler.tick(), 3);
+        assert_eq!((fast.count(), slow.count(), late.count()), (5, 1, 2));
     }
 }
@@ -15,10 +15,16 @@
 //! 实现时间序列数据存储和展示
 
 use std::fmt;
-use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::ops::Div;
+use std::sync::Arc;
+use num_traits::FromPrimitive;
 use parking_lot::RwLock;
 
-use crate::variable::SeriesOptions;
+use crate::detail::bounded_queue::BoundedQueue;
+use crate::detail::clock::{system_clock, Clock};
+
+use crate::export::{escape_json, is_json_number};
+use crate::variable::{SeriesGranularity, SeriesOptions};
 use crate::window::{SERIES_IN_SECOND, SERIES_IN_MINUTE, SERIES_IN_HOUR, SERIES_IN_DAY};
 
 use crate::detail::combiner::Combiner;
@@ -40,31 +46,74 @@ impl<T> DataPoint<T> {
     
     /// 使用当前时间创建数据点
     pub fn now(value: T) -> Self {
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_else(|_| Duration::from_secs(0))
-            .as_millis() as u64;
-        
-        Self { value, timestamp: now }
+        Self::now_with(value, system_clock().as_ref())
+    }
+
+    /// 使用`clock`的当前时间创建数据点
+    pub fn now_with(value: T, clock: &dyn Clock) -> Self {
+        Self {
+            value,
+            timestamp: clock.now_ms(),
+        }
     }
 }
 
-/// 表示一个时间序列
-pub struct Series<T, Op> {
+/// 各个粒度的数据点
+struct SeriesData<T> {
     /// 秒级数据，最近60秒
-    second_points: RwLock<Vec<DataPoint<T>>>,
+    second: BoundedQueue<DataPoint<T>>,
     /// 分钟级数据，最近60分钟
-    minute_points: RwLock<Vec<DataPoint<T>>>,
+    minute: BoundedQueue<DataPoint<T>>,
     /// 小时级数据，最近24小时
-    hour_points: RwLock<Vec<DataPoint<T>>>,
+    hour: BoundedQueue<DataPoint<T>>,
     /// 天级数据，最近30天
-    day_points: RwLock<Vec<DataPoint<T>>>,
+    day: BoundedQueue<DataPoint<T>>,
+    /// 距离上一个分钟点新增的秒级数据点数量
+    pending_seconds: usize,
+    /// 距离上一个小时点新增的分钟级数据点数量
+    pending_minutes: usize,
+    /// 距离上一个天级点新增的小时级数据点数量
+    pending_hours: usize,
+}
+
+impl<T> SeriesData<T> {
+    /// 某个粒度的数据点
+    fn queue(&self, granularity: SeriesGranularity) -> &BoundedQueue<DataPoint<T>> {
+        match granularity {
+            SeriesGranularity::Second => &self.second,
+            SeriesGranularity::Minute => &self.minute,
+            SeriesGranularity::Hour => &self.hour,
+            SeriesGranularity::Day => &self.day,
+        }
+    }
+}
+
+/// 表示一个时间序列，类似bvar的`Series`
+///
+/// 采样线程每秒调用一次`append`。每满60个秒级数据点合并为一个分钟级数据点，
+/// 每满60个分钟级数据点合并为一个小时级数据点，每满24个小时级数据点合并为一个天级数据点。
+/// 合并方式默认为`Op`，如求和、求最大值；记录瞬时值的序列应该用[`Series::averaged`]取平均。
+/// 各个粒度都保存在容量固定的环形队列中。
+pub struct Series<T, Op> {
+    /// 各个粒度的数据点
+    data: RwLock<SeriesData<T>>,
     /// 组合操作符
     op: Op,
-    /// 最后一次添加的数据点
-    last_point: RwLock<Option<DataPoint<T>>>,
-    /// 上次采样时间
-    last_sample_time: RwLock<Option<Instant>>,
+    /// 合并后除以数据点数量，为`None`时只用`op`合并
+    divide: Option<fn(T, usize) -> T>,
+    /// 计算时间戳的时钟
+    clock: Arc<dyn Clock>,
+}
+
+/// 求平均值时除以数据点数量
+fn divide_by_count<T>(value: T, count: usize) -> T
+where
+    T: Div<Output = T> + FromPrimitive,
+{
+    match T::from_usize(count) {
+        Some(count) => value / count,
+        None => value,
+    }
 }
 
 impl<T, Op> Series<T, Op>
@@ -72,116 +121,189 @@ where
     T: Clone + fmt::Debug + Send + Sync + 'static,
     Op: Combiner<T> + Clone + Send + Sync + 'static,
 {
-    /// 创建新的时间序列
+    /// 创建用`op`合并数据点的时间序列
     pub fn new(op: Op) -> Self {
+        Self::with_clock(op, system_clock())
+    }
+
+    /// 创建用`clock`计时的时间序列
+    pub fn with_clock(op: Op, clock: Arc<dyn Clock>) -> Self {
         Self {
-            second_points: RwLock::new(Vec::with_capacity(SERIES_IN_SECOND)),
-            minute_points: RwLock::new(Vec::with_capacity(SERIES_IN_MINUTE)),
-            hour_points: RwLock::new(Vec::with_capacity(SERIES_IN_HOUR)),
-            day_points: RwLock::new(Vec::with_capacity(SERIES_IN_DAY)),
+            data: RwLock::new(SeriesData {
+                second: BoundedQueue::new(SERIES_IN_SECOND),
+                minute: BoundedQueue::new(SERIES_IN_MINUTE),
+                hour: BoundedQueue::new(SERIES_IN_HOUR),
+                day: BoundedQueue::new(SERIES_IN_DAY),
+                pending_seconds: 0,
+                pending_minutes: 0,
+                pending_hours: 0,
+            }),
             op,
-            last_point: RwLock::new(None),
-            last_sample_time: RwLock::new(None),
+            divide: None,
+            clock,
         }
     }
-    
-    /// 添加一个数据点
-    pub fn append(&self, value: T) {
-        let point = DataPoint::now(value);
-        
-        // 更新最后的数据点
-        *self.last_point.write() = Some(point.clone());
-        
-        // 检查是否需要采样
-        let now = Instant::now();
-        let mut should_sample_second = true;
-        let mut should_sample_minute = false;
-        let mut should_sample_hour = false;
-        let mut should_sample_day = false;
-        
-        if let Some(last_time) = *self.last_sample_time.read() {
-            let elapsed = now.duration_since(last_time);
-            should_sample_second = elapsed >= Duration::from_secs(1);
-            should_sample_minute = elapsed >= Duration::from_secs(60);
-            should_sample_hour = elapsed >= Duration::from_secs(3600);
-            should_sample_day = elapsed >= Duration::from_secs(86400);
-        }
-        
-        // 更新采样时间
-        if should_sample_second {
-            *self.last_sample_time.write() = Some(now);
-        }
-        
-        // 添加到不同时间粒度的序列中
-        if should_sample_second {
-            self.add_to_series(&self.second_points, point.clone(), SERIES_IN_SECOND);
+
+    /// 创建用`op`求和后取平均值的时间序列，用于记录瞬时值的变量
+    pub fn averaged(op: Op) -> Self
+    where
+        T: Div<Output = T> + FromPrimitive,
+    {
+        Self::averaged_with_clock(op, system_clock())
+    }
+
+    /// 创建用`clock`计时、取平均值的时间序列
+    pub fn averaged_with_clock(op: Op, clock: Arc<dyn Clock>) -> Self
+    where
+        T: Div<Output = T> + FromPrimitive,
+    {
+        Self {
+            divide: Some(divide_by_count::<T>),
+            ..Self::with_clock(op, clock)
         }
-        
-        if should_sample_minute {
-            self.add_to_series(&self.minute_points, point.clone(), SERIES_IN_MINUTE);
+    }
+
+    /// 添加一个秒级数据点，攒够一个更粗的粒度时合并
+    pub fn append(&self, value: T) {
+        let point = DataPoint::now_with(value, self.clock.as_ref());
+        let mut data = self.data.write();
+        let data = &mut *data;
+
+        data.second.elim_push(point);
+        data.pending_seconds += 1;
+        if data.pending_seconds < SERIES_IN_SECOND {
+            return;
         }
-        
-        if should_sample_hour {
-            self.add_to_series(&self.hour_points, point.clone(), SERIES_IN_HOUR);
+        data.pending_seconds = 0;
+        let minute = self.reduce(&data.second, SERIES_IN_SECOND);
+        data.minute.elim_push(minute);
+
+        data.pending_minutes += 1;
+        if data.pending_minutes < SERIES_IN_MINUTE {
+            return;
         }
-        
-        if should_sample_day {
-            self.add_to_series(&self.day_points, point, SERIES_IN_DAY);
+        data.pending_minutes = 0;
+        let hour = self.reduce(&data.minute, SERIES_IN_MINUTE);
+        data.hour.elim_push(hour);
+
+        data.pending_hours += 1;
+        if data.pending_hours < SERIES_IN_HOUR {
+            return;
         }
+        data.pending_hours = 0;
+        let day = self.reduce(&data.hour, SERIES_IN_HOUR);
+        data.day.elim_push(day);
     }
-    
-    /// 添加到指定的时间序列
-    fn add_to_series(&self, series: &RwLock<Vec<DataPoint<T>>>, point: DataPoint<T>, max_size: usize) {
-        let mut series = series.write();
-        
-        // 添加新点
-        series.push(point);
-        
-        // 如果超过容量，移除最旧的点
-        if series.len() > max_size {
-            series.remove(0);
-        }
+
+    /// 合并最新的`count`个数据点，时间戳取最新的数据点
+    fn reduce(&self, points: &BoundedQueue<DataPoint<T>>, count: usize) -> DataPoint<T> {
+        let mut recent = points.iter().rev().take(count);
+        let latest = recent.next().expect("series to reduce must not be empty").clone();
+        let mut reduced = 1;
+        let value = recent.fold(latest.value, |value, point| {
+            reduced += 1;
+            self.op.combine(value, point.value.clone())
+        });
+        let value = match self.divide {
+            Some(divide) => divide(value, reduced),
+            None => value,
+        };
+        DataPoint::new(value, latest.timestamp)
     }
-    
+
     /// 获取最后一个数据点
     pub fn last_point(&self) -> Option<DataPoint<T>> {
-        self.last_point.read().clone()
+        self.data.read().second.top(0).cloned()
     }
-    
+
+    /// 获取秒级数据点，从旧到新排列
+    pub fn second_points(&self) -> Vec<DataPoint<T>> {
+        self.points(SeriesGranularity::Second)
+    }
+
+    /// 获取分钟级数据点，从旧到新排列
+    pub fn minute_points(&self) -> Vec<DataPoint<T>> {
+        self.points(SeriesGranularity::Minute)
+    }
+
+    /// 获取小时级数据点，从旧到新排列
+    pub fn hour_points(&self) -> Vec<DataPoint<T>> {
+        self.points(SeriesGranularity::Hour)
+    }
+
+    /// 获取天级数据点，从旧到新排列
+    pub fn day_points(&self) -> Vec<DataPoint<T>> {
+        self.points(SeriesGranularity::Day)
+    }
+
+    /// 获取某个粒度的数据点，从旧到新排列
+    pub fn points(&self, granularity: SeriesGranularity) -> Vec<DataPoint<T>> {
+        let data = self.data.read();
+        data.queue(granularity).iter().cloned().collect()
+    }
+
+    /// 按`options`获取某个粒度要输出的数据点
+    ///
+    /// `fixed_length`时在最旧的数据点之前补零，时间戳按粒度的间隔向前推算；
+    /// 之后超过`max_length`的最旧的数据点被丢弃
+    fn points_to_describe(&self, granularity: SeriesGranularity, options: &SeriesOptions) -> Vec<DataPoint<T>>
+    where
+        T: Default,
+    {
+        let (mut points, capacity) = {
+            let data = self.data.read();
+            let queue = data.queue(granularity);
+            (queue.iter().cloned().collect::<Vec<_>>(), queue.capacity())
+        };
+        if options.fixed_length && points.len() < capacity {
+            let step = granularity.interval().as_millis() as u64;
+            let oldest = match points.first() {
+                Some(point) => point.timestamp.saturating_sub(step),
+                None => self.clock.now_ms(),
+            };
+            let missing = capacity - points.len();
+            let padding = (0..missing)
+                .rev()
+                .map(|i| DataPoint::new(T::default(), oldest.saturating_sub(step * i as u64)));
+            points.splice(0..0, padding);
+        }
+        if let Some(max_length) = options.max_length {
+            let excess = points.len().saturating_sub(max_length);
+            points.drain(..excess);
+        }
+        points
+    }
+
     /// 描述序列数据为JSON格式
-    pub fn describe(&self, f: &mut dyn fmt::Write, options: &SeriesOptions) {
-        // 获取所有数据序列的快照
-        let second_points = self.second_points.read().clone();
-        let minute_points = self.minute_points.read().clone();
-        let hour_points = self.hour_points.read().clone();
-        let day_points = self.day_points.read().clone();
-        
-        // 创建JSON对象
-        let _ = write!(f, "{{");
-        
-        // 添加元数据
-        let _ = write!(f, "\"meta\":{{\"name\":\"time_series\",\"fixed_length\":{}}},", 
-                      if options.fixed_length { "true" } else { "false" });
-        
-        // 添加各个时间粒度的数据
-        let _ = write!(f, "\"data\":{{");
-        
-        // 秒级数据
-        self.describe_series_data(f, "second", &second_points);
-        let _ = write!(f, ",");
-        
-        // 分钟级数据
-        self.describe_series_data(f, "minute", &minute_points);
-        let _ = write!(f, ",");
-        
-        // 小时级数据
-        self.describe_series_data(f, "hour", &hour_points);
-        let _ = write!(f, ",");
-        
-        // 天级数据
-        self.describe_series_data(f, "day", &day_points);
-        
-        // 结束JSON对象
+    pub fn describe(&self, f: &mut dyn fmt::Write, options: &SeriesOptions)
+    where
+        T: Default,
+    {
+        self.describe_with(f, options, None);
+    }
+
+    /// 描述序列数据为JSON格式，`options.include_description`为true时带上变量的当前描述
+    pub fn describe_with(&self, f: &mut dyn fmt::Write, options: &SeriesOptions, description: Option<&str>)
+    where
+        T: Default,
+    {
+        let _ = write!(
+            f,
+            "{{\"meta\":{{\"name\":\"time_series\",\"fixed_length\":{}}}",
+            options.fixed_length
+        );
+        if let (true, Some(description)) = (options.include_description, description) {
+            let _ = write!(f, ",\"description\":\"{}\"", escape_json(description));
+        }
+
+        let _ = write!(f, ",\"data\":{{");
+        for (i, granularity) in options.granularities.iter().enumerate() {
+            if i > 0 {
+                let _ = write!(f, ",");
+            }
+            let points = self.points_to_describe(*granularity, options);
+            self.describe_series_data(f, granularity.name(), &points);
+        }
         let _ = write!(f, "}}}}");
     }
     
@@ -208,7 +330,13 @@ where
                 let _ = write!(f, ",");
             }
             first = false;
-            let _ = write!(f, "{:?}", point.value);
+            // NaN、inf等不是合法的JSON数字，输出为null
+            let value = format!("{:?}", point.value);
+            let _ = if is_json_number(&value) {
+                write!(f, "{}", value)
+            } else {
+                write!(f, "null")
+            };
         }
         
         let _ = write!(f, "]}}");
@@ -236,7 +364,7 @@ where
 
 impl<'a, T, Op> fmt::Display for SeriesFormatter<'a, T, Op>
 where
-    T: Clone + fmt::Debug + Send + Sync + 'static,
+    T: Clone + Default + fmt::Debug + Send + Sync + 'static,
     Op: Combiner<T> + Clone + Send + Sync + 'static,
 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -250,23 +378,114 @@ where
 mod tests {
     use super::*;
     use crate::variable::SeriesOptions;
-    use crate::reducer::AddTo;
+    use crate::detail::clock::MockClock;
+    use crate::reducer::{AddTo, MaxTo};
+    use std::time::Duration;
+    use std::time::UNIX_EPOCH;
 
     #[test]
     fn test_series() {
-        let series = Series::new(AddTo::default());
-        series.append(1);
+        let clock = Arc::new(MockClock::starting_at(UNIX_EPOCH + Duration::from_secs(100)));
+        let series = Series::with_clock(AddTo::default(), clock.clone());
+        for value in 1..=3 {
+            series.append(value);
+            clock.advance(Duration::from_secs(1));
+        }
+        assert_eq!(series.last_point().unwrap().timestamp, 102_000);
+
+        let options = SeriesOptions::new().with_fixed_length(false);
+        let mut buf = String::new();
+        series.describe(&mut buf, &options);
+        assert!(
+            buf.contains(r#""second":{"timestamps":[100000,101000,102000],"values":[1,2,3]}"#),
+            "{}",
+            buf
+        );
+        assert_eq!(SeriesFormatter::new(&series, options).to_string(), buf);
+    }
 
-        /// sleep 1秒
-        std::thread::sleep(Duration::from_secs(1));
-        series.append(2);
-        series.append(3);
-        let formatter = SeriesFormatter::new(&series, SeriesOptions::default());
-        println!("{}", formatter);
+    #[test]
+    fn test_series_options() {
+        let clock = Arc::new(MockClock::starting_at(UNIX_EPOCH + Duration::from_secs(1000)));
+        let series = Series::with_clock(AddTo::default(), clock.clone());
+        for value in 1..=3 {
+            series.append(value);
+            clock.advance(Duration::from_secs(1));
+        }
+        let describe = |options: &SeriesOptions, description: Option<&str>| {
+            let mut buf = String::new();
+            series.describe_with(&mut buf, options, description);
+            buf
+        };
 
+        // 固定长度时在前面补零，时间戳按间隔向前推算
+        let fixed = describe(&SeriesOptions::default(), None);
+        assert!(fixed.contains(r#""timestamps":[943000,944000,945000,"#), "{}", fixed);
+        assert!(fixed.contains(r#",0,0,1,2,3]}"#), "{}", fixed);
+        assert_eq!(series.second_points().len(), 3);
+
+        // 截断最旧的数据点，只输出选中的粒度
+        let options = SeriesOptions::new()
+            .with_max_length(Some(2))
+            .with_granularities(&[SeriesGranularity::Second, SeriesGranularity::Minute]);
+        assert_eq!(
+            describe(&options, Some("3")),
+            concat!(
+                r#"{"meta":{"name":"time_series","fixed_length":true},"description":"3","data":{"#,
+                r#""second":{"timestamps":[1001000,1002000],"values":[2,3]},"#,
+                r#""minute":{"timestamps":[943000,1003000],"values":[0,0]}}}"#
+            )
+        );
+        let options = options.with_description(false).with_fixed_length(false);
+        assert_eq!(
+            describe(&options, Some("3")),
+            concat!(
+                r#"{"meta":{"name":"time_series","fixed_length":false},"data":{"#,
+                r#""second":{"timestamps":[1001000,1002000],"values":[2,3]},"#,
+                r#""minute":{"timestamps":[],"values":[]}}}"#
+            )
+        );
+
+        // 非有限的浮点数输出为null，保证输出是合法的JSON
+        let floats = Series::with_clock(AddTo::default(), clock.clone());
+        for value in [1.5, f64::NAN, f64::INFINITY] {
+            floats.append(value);
+            clock.advance(Duration::from_secs(1));
+        }
         let mut buf = String::new();
-        series.describe(&mut buf, &SeriesOptions::default());
-        println!("{}", buf);
-        
+        floats.describe_with(&mut buf, &options.with_max_length(None), None);
+        assert!(buf.contains(r#""values":[1.5,null,null]"#), "{}", buf);
+    }
+
+    #[test]
+    fn test_series_downsampling() {
+        let clock = Arc::new(MockClock::new());
+        let maxes = Series::with_clock(MaxTo::default(), clock.clone());
+        let averages = Series::averaged_with_clock(AddTo::default(), clock.clone());
+        // 两天零一小时，每秒一个点，值为当天经过的秒数
+        let seconds = 2 * 86400 + 3600;
+        for second in 0..seconds {
+            maxes.append(second % 86400);
+            averages.append(second % 86400);
+            clock.advance(Duration::from_secs(1));
+        }
+
+        assert_eq!(maxes.second_points().len(), SERIES_IN_SECOND);
+        assert_eq!(maxes.minute_points().len(), SERIES_IN_MINUTE);
+        assert_eq!(maxes.hour_points().len(), SERIES_IN_HOUR);
+        assert_eq!(maxes.day_points().len(), 2);
+
+        // 第一天的最大值是最后一秒，平均值是中间值
+        let days: Vec<i64> = maxes.day_points().iter().map(|p| p.value).collect();
+        assert_eq!(days, vec![86399, 86399]);
+        let days: Vec<i64> = averages.day_points().iter().map(|p| p.value).collect();
+        assert_eq!(days, vec![43199, 43199]);
+
+        // 最后一个小时是第三天的第一个小时
+        let last_hour = averages.hour_points().last().unwrap().clone();
+        assert_eq!(last_hour.value, 1799);
+        assert_eq!(last_hour.timestamp, maxes.last_point().unwrap().timestamp);
+        let last_minute = maxes.minute_points().last().unwrap().value;
+        assert_eq!(last_minute, 3599);
     }
-}
\ No newline at end of file
+}
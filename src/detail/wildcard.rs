@@ -0,0 +1,140 @@
+// Copyright 2025 KenForever1
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! 实现bvar风格的通配符匹配，`*`匹配任意个字符，`?`匹配单个字符
+
+use std::collections::HashSet;
+
+/// 检查`name`是否匹配通配符`pattern`
+///
+/// `question_mark`是匹配单个字符的符号，默认是`?`，
+/// 在URL中可以换成`$`之类的字符以避免和查询参数冲突。
+pub fn wildcard_match(pattern: &str, name: &str, question_mark: char) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    let (mut p, mut n) = (0, 0);
+    // 最近一个`*`的位置以及当时匹配到的name位置，用于回溯
+    let mut star: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == question_mark || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = star {
+            // 让`*`多吞一个字符再试
+            p = star_p + 1;
+            n = star_n + 1;
+            star = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+
+    while p < pattern.len() && pattern[p] == '*' {
+        p += 1;
+    }
+    p == pattern.len()
+}
+
+/// 由`;`或`,`分隔的一组通配符
+#[derive(Debug, Clone)]
+pub struct WildcardMatcher {
+    /// 不含通配符的名称，直接查表
+    exact_names: HashSet<String>,
+    /// 含有通配符的模式
+    wildcards: Vec<String>,
+    /// 匹配单个字符的符号
+    question_mark: char,
+    /// 没有任何模式时的匹配结果
+    on_both_empty: bool,
+}
+
+impl WildcardMatcher {
+    /// 解析通配符列表
+    pub fn new(wildcards: &str, question_mark: char, on_both_empty: bool) -> Self {
+        let mut exact_names = HashSet::new();
+        let mut patterns = Vec::new();
+
+        for item in wildcards.split([';', ',']) {
+            let item = item.trim();
+            if item.is_empty() {
+                continue;
+            }
+            if item.contains('*') || item.contains(question_mark) {
+                patterns.push(item.to_string());
+            } else {
+                exact_names.insert(item.to_string());
+            }
+        }
+
+        Self {
+            exact_names,
+            wildcards: patterns,
+            question_mark,
+            on_both_empty,
+        }
+    }
+
+    /// 检查名称是否匹配其中任意一个模式
+    pub fn matches(&self, name: &str) -> bool {
+        if self.is_empty() {
+            return self.on_both_empty;
+        }
+        self.exact_names.contains(name)
+            || self
+                .wildcards
+                .iter()
+                .any(|pattern| wildcard_match(pattern, name, self.question_mark))
+    }
+
+    /// 是否没有任何模式
+    pub fn is_empty(&self) -> bool {
+        self.exact_names.is_empty() && self.wildcards.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("*", "", '?'));
+        assert!(wildcard_match("*", "anything", '?'));
+        assert!(wildcard_match("rpc_*_latency*", "rpc_server_latency_99", '?'));
+        assert!(wildcard_match("rpc_?", "rpc_a", '?'));
+        assert!(!wildcard_match("rpc_?", "rpc_ab", '?'));
+        assert!(wildcard_match("rpc_$", "rpc_a", '$'));
+        assert!(!wildcard_match("rpc_?", "rpc_a", '$'));
+        assert!(wildcard_match("*_debug", "a_debug_debug", '?'));
+        assert!(!wildcard_match("*_debug", "a_debug_x", '?'));
+        assert!(wildcard_match("a*b*c", "aXXbYYbc", '?'));
+    }
+
+    #[test]
+    fn test_wildcard_matcher() {
+        let matcher = WildcardMatcher::new("rpc_*; process_cpu ,*_qps", '?', false);
+        assert!(matcher.matches("rpc_count"));
+        assert!(matcher.matches("process_cpu"));
+        assert!(matcher.matches("server_qps"));
+        assert!(!matcher.matches("process_cpu_usage"));
+
+        assert!(WildcardMatcher::new("", '?', true).matches("x"));
+        assert!(!WildcardMatcher::new(" ; ", '?', false).matches("x"));
+    }
+}
@@ -0,0 +1,408 @@
+// Copyright 2025 KenForever1
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! 定期把所有暴露的变量写入文件，类似bvar的`bvar_dump_file`
+//!
+//! 每次都先写入临时文件再重命名为目标文件，读者不会看到写了一半的内容。
+
+use std::fmt::{self, Write as _};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Weak};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use parking_lot::{Condvar, Mutex};
+
+use crate::detail::clock::system_clock;
+use crate::detail::sampler::{GlobalSamplerState, Sampler};
+use crate::export::{escape_json, write_json_value};
+use crate::variable::{dump_exposed, DumpOptions};
+
+/// 文件的格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpFormat {
+    /// 每行`name : value`
+    Text,
+    /// 一个以变量名为键的JSON对象
+    Json,
+}
+
+/// 文件dump的选项
+#[derive(Debug, Clone)]
+pub struct FileDumpOptions {
+    /// 目标文件路径
+    pub path: PathBuf,
+    /// dump的间隔
+    pub interval: Duration,
+    /// 文件格式
+    pub format: DumpFormat,
+    /// 加在每个变量名之前的前缀，为空时不加
+    pub prefix: String,
+    /// 保留的历史文件数量，历史文件依次命名为`<path>.1`、`<path>.2`...
+    pub max_backups: usize,
+    /// 过滤变量的选项
+    pub dump_options: DumpOptions,
+}
+
+impl Default for FileDumpOptions {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("monitor/bvar.data"),
+            interval: Duration::from_secs(10),
+            format: DumpFormat::Text,
+            prefix: String::new(),
+            max_backups: 0,
+            dump_options: DumpOptions::new().with_quote_string(false),
+        }
+    }
+}
+
+impl FileDumpOptions {
+    /// 创建写入`path`的选项
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            ..Self::default()
+        }
+    }
+
+    /// 设置dump的间隔
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// 设置文件格式
+    pub fn with_format(mut self, format: DumpFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// 设置变量名的前缀
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+
+    /// 设置保留的历史文件数量
+    pub fn with_max_backups(mut self, max_backups: usize) -> Self {
+        self.max_backups = max_backups;
+        self
+    }
+
+    /// 设置白名单通配符
+    pub fn with_include(mut self, wildcards: &str) -> Self {
+        self.dump_options.white_wildcards = wildcards.to_string();
+        self
+    }
+
+    /// 设置黑名单通配符
+    pub fn with_exclude(mut self, wildcards: &str) -> Self {
+        self.dump_options.black_wildcards = wildcards.to_string();
+        self
+    }
+}
+
+/// 把匹配的变量写入文件一次，返回写入的变量数量
+pub fn dump_to_file(options: &FileDumpOptions) -> io::Result<usize> {
+    let mut content = String::new();
+    let mut count = 0;
+    let json = options.format == DumpFormat::Json;
+    if json {
+        content.push('{');
+    }
+
+    dump_exposed(&options.dump_options, &mut |name: &str, description: &str| {
+        let name = if options.prefix.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}_{}", options.prefix, name)
+        };
+        if json {
+            if count > 0 {
+                content.push(',');
+            }
+            let _ = write!(content, "\"{}\":", escape_json(&name));
+            write_json_value(&mut content, description);
+        } else {
+            let _ = writeln!(content, "{} : {}", name, description);
+        }
+        count += 1;
+        true
+    });
+
+    if json {
+        content.push_str("}\n");
+    }
+    write_atomically(&options.path, content.as_bytes(), options.max_backups)?;
+    Ok(count)
+}
+
+/// 先写入同目录下的临时文件，再重命名为目标文件
+fn write_atomically(path: &Path, content: &[u8], max_backups: usize) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        if !dir.as_os_str().is_empty() {
+            fs::create_dir_all(dir)?;
+        }
+    }
+
+    let tmp_path = with_suffix(path, "tmp");
+    {
+        let mut file = fs::File::create(&tmp_path)?;
+        file.write_all(content)?;
+        file.sync_all()?;
+    }
+
+    if max_backups > 0 && path.exists() {
+        // 从最旧的开始依次后移，<path>.N会被覆盖
+        for i in (1..max_backups).rev() {
+            let from = with_suffix(path, &i.to_string());
+            if from.exists() {
+                fs::rename(&from, with_suffix(path, &(i + 1).to_string()))?;
+            }
+        }
+        // 用硬链接保留旧文件，目标文件在任何时刻都存在
+        let backup = with_suffix(path, "1");
+        if fs::hard_link(path, &backup).is_err() {
+            fs::copy(path, &backup)?;
+        }
+    }
+
+    fs::rename(&tmp_path, path)
+}
+
+/// 在文件名后面追加`.suffix`
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".");
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// 每隔`options.interval`dump一次的采样器
+struct FileDumpSampler {
+    /// dump的选项
+    options: FileDumpOptions,
+    /// 是否已经停止
+    stopped: AtomicBool,
+}
+
+impl Sampler for FileDumpSampler {
+    fn interval(&self) -> Duration {
+        self.options.interval
+    }
+
+    fn take_sample(&self) {
+        if self.stopped.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Err(e) = dump_to_file(&self.options) {
+            log::warn!("Fail to dump variables into {}: {}", self.options.path.display(), e);
+        }
+    }
+
+    fn describe(&self, f: &mut dyn fmt::Write) {
+        let _ = write!(f, "dump into {}", self.options.path.display());
+    }
+
+    fn destroy(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+}
+
+/// 在独立线程中定期dump，销毁时停止
+///
+/// dump需要写文件和fsync，不放在全局采样线程中，避免磁盘较慢时推迟其它变量的采样。
+pub struct FileDumper {
+    /// 采样器，只被这里持有，释放后调度器自动清理
+    sampler: Option<Arc<FileDumpSampler>>,
+    /// 是否已经停止，用于唤醒dump线程
+    stopped: Arc<(Mutex<bool>, Condvar)>,
+    /// dump线程
+    handle: Option<JoinHandle<()>>,
+}
+
+impl FileDumper {
+    /// 创建还没有被调度的dumper，需要调用[`FileDumper::register`]
+    pub fn new(options: FileDumpOptions) -> Self {
+        Self {
+            sampler: Some(Arc::new(FileDumpSampler {
+                options,
+                stopped: AtomicBool::new(false),
+            })),
+            stopped: Arc::new((Mutex::new(false), Condvar::new())),
+            handle: None,
+        }
+    }
+
+    /// 立即dump一次，返回写入的变量数量
+    pub fn dump(&self) -> io::Result<usize> {
+        match &self.sampler {
+            Some(sampler) => dump_to_file(&sampler.options),
+            None => Ok(0),
+        }
+    }
+
+    /// 由`scheduler`每隔`options.interval`dump一次
+    pub fn register(&self, scheduler: &mut GlobalSamplerState) {
+        if let Some(sampler) = &self.sampler {
+            let weak: Weak<dyn Sampler> = Arc::downgrade(sampler) as Weak<FileDumpSampler>;
+            scheduler.register_sampler(weak);
+        }
+    }
+
+    /// 启动dump线程，线程中用独立的调度器驱动采样器
+    fn spawn(&mut self) -> io::Result<()> {
+        let mut scheduler = GlobalSamplerState::new_manual(system_clock());
+        self.register(&mut scheduler);
+        let stopped = self.stopped.clone();
+        let handle = thread::Builder::new()
+            .name("bvar_file_dumper".to_string())
+            .spawn(move || {
+                let (lock, condvar) = &*stopped;
+                loop {
+                    scheduler.tick();
+                    // 采样器被释放后调度器中没有采样器，线程退出
+                    let Some(next_time) = scheduler.next_sample_time() else {
+                        break;
+                    };
+                    let mut stopped = lock.lock();
+                    if !*stopped {
+                        condvar.wait_until(&mut stopped, next_time);
+                    }
+                    if *stopped {
+                        break;
+                    }
+                }
+            })?;
+        self.handle = Some(handle);
+        Ok(())
+    }
+
+    /// 停止dump并等待线程退出，正在进行的dump会完成
+    pub fn stop(&mut self) {
+        if let Some(sampler) = self.sampler.take() {
+            sampler.destroy();
+        }
+        let (lock, condvar) = &*self.stopped;
+        *lock.lock() = true;
+        condvar.notify_all();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for FileDumper {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// 立即dump一次，之后在独立线程中每隔`options.interval`把变量写入文件
+///
+/// 第一次dump失败或者无法创建线程时返回错误
+pub fn start_file_dumper(options: FileDumpOptions) -> io::Result<FileDumper> {
+    let mut dumper = FileDumper::new(options);
+    dumper.dump()?;
+    dumper.spawn()?;
+    Ok(dumper)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::detail::clock::MockClock;
+    use crate::status::Status;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("bvar_file_test_{}", std::process::id()))
+            .join(name)
+    }
+
+    #[test]
+    fn test_dump_to_file() {
+        let _count = Status::with_name(5, "file_test_count").unwrap();
+        let _state = Status::with_name("ok \"fine\"".to_string(), "file_test_state").unwrap();
+
+        let path = temp_path("text.data");
+        let options = FileDumpOptions::new(&path).with_include("file_test_*").with_prefix("app");
+        assert_eq!(dump_to_file(&options).unwrap(), 2);
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "app_file_test_count : 5\napp_file_test_state : ok \"fine\"\n"
+        );
+
+        let path = temp_path("json.data");
+        let options = FileDumpOptions::new(&path)
+            .with_include("file_test_*")
+            .with_format(DumpFormat::Json);
+        dump_to_file(&options).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "{\"file_test_count\":5,\"file_test_state\":\"ok \\\"fine\\\"\"}\n"
+        );
+        assert!(!with_suffix(&path, "tmp").exists());
+    }
+
+    #[test]
+    fn test_file_dumper() {
+        let status = Status::with_name(1, "file_dumper_test_value").unwrap();
+        let path = temp_path("rotate.data");
+        let options = FileDumpOptions::new(&path)
+            .with_include("file_dumper_test_*")
+            .with_interval(Duration::from_millis(20))
+            .with_max_backups(2);
+
+        let clock = Arc::new(MockClock::new());
+        let mut scheduler = GlobalSamplerState::new_manual(clock.clone());
+        let mut dumper = FileDumper::new(options);
+        assert_eq!(dumper.dump().unwrap(), 1);
+        dumper.register(&mut scheduler);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "file_dumper_test_value : 1\n");
+
+        // 每个间隔dump一次，已有的文件依次后移
+        for _ in 0..3 {
+            clock.advance(Duration::from_millis(20));
+            assert_eq!(scheduler.tick(), 1);
+        }
+        status.set_value(2);
+        clock.advance(Duration::from_millis(20));
+        scheduler.tick();
+        dumper.stop();
+        status.set_value(3);
+        clock.advance(Duration::from_millis(20));
+        assert_eq!(scheduler.tick(), 0);
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "file_dumper_test_value : 2\n");
+        assert!(with_suffix(&path, "1").exists());
+        assert!(with_suffix(&path, "2").exists());
+        assert!(!with_suffix(&path, "3").exists());
+
+        // 停止时唤醒dump线程，不需要等待一个间隔
+        let thread_path = temp_path("thread.data");
+        let mut dumper = start_file_dumper(FileDumpOptions::new(&thread_path)).unwrap();
+        assert!(thread_path.exists());
+        dumper.stop();
+
+        // 第一次dump的错误会被返回
+        let dir = temp_path("dumper_dir");
+        fs::create_dir_all(&dir).unwrap();
+        assert!(start_file_dumper(FileDumpOptions::new(&dir)).is_err());
+    }
+}
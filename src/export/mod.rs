@@ -0,0 +1,168 @@
+// Copyright 2025 KenForever1
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! 把暴露的变量导出到外部监控系统
+
+use std::fmt::Write;
+
+pub mod file;
+pub mod prometheus;
+
+/// 变量导出到监控系统时的类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricKind {
+    /// 只增不减的计数
+    Counter,
+    /// 可以任意变化的数值
+    Gauge,
+    /// 带有`_sum`、`_count`以及分位值的统计
+    Summary,
+    /// 带有`_bucket`、`_sum`、`_count`的分布
+    Histogram,
+}
+
+impl MetricKind {
+    /// 获取类型的名称
+    pub fn name(&self) -> &'static str {
+        match self {
+            MetricKind::Counter => "counter",
+            MetricKind::Gauge => "gauge",
+            MetricKind::Summary => "summary",
+            MetricKind::Histogram => "histogram",
+        }
+    }
+}
+
+/// 变量导出的一个样本
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricSample {
+    /// 追加在变量名之后的后缀，如`_sum`
+    pub suffix: String,
+    /// 样本的标签
+    pub labels: Vec<(String, String)>,
+    /// 样本的值
+    pub value: f64,
+}
+
+impl MetricSample {
+    /// 创建没有后缀和标签的样本
+    pub fn new(value: f64) -> Self {
+        Self {
+            suffix: String::new(),
+            labels: Vec::new(),
+            value,
+        }
+    }
+
+    /// 设置后缀
+    pub fn with_suffix(mut self, suffix: &str) -> Self {
+        self.suffix = suffix.to_string();
+        self
+    }
+
+    /// 添加一个标签
+    pub fn with_label(mut self, name: &str, value: &str) -> Self {
+        self.labels.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+/// 转义JSON字符串中的特殊字符
+pub fn escape_json(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(result, "\\u{:04x}", c as u32);
+            }
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// 检查`s`是否符合JSON数字的语法，如`-1.5e3`
+///
+/// Rust能解析的`+5`、`.5`、`5.`、`inf`等都不是合法的JSON数字
+pub fn is_json_number(s: &str) -> bool {
+    let bytes = s.as_bytes();
+    let mut i = 0;
+    let digits = |i: &mut usize| {
+        let start = *i;
+        while *i < bytes.len() && bytes[*i].is_ascii_digit() {
+            *i += 1;
+        }
+        *i - start
+    };
+
+    if bytes.first() == Some(&b'-') {
+        i += 1;
+    }
+    // 整数部分不能有多余的前导零
+    match bytes.get(i) {
+        Some(b'0') => i += 1,
+        Some(b'1'..=b'9') => {
+            digits(&mut i);
+        }
+        _ => return false,
+    }
+    if bytes.get(i) == Some(&b'.') {
+        i += 1;
+        if digits(&mut i) == 0 {
+            return false;
+        }
+    }
+    if matches!(bytes.get(i), Some(b'e' | b'E')) {
+        i += 1;
+        if matches!(bytes.get(i), Some(b'+' | b'-')) {
+            i += 1;
+        }
+        if digits(&mut i) == 0 {
+            return false;
+        }
+    }
+    i == bytes.len()
+}
+
+/// 把变量的描述作为JSON值写入`f`，合法的JSON数字原样输出，其它值输出为字符串
+pub fn write_json_value(f: &mut String, description: &str) {
+    let _ = if is_json_number(description) {
+        write!(f, "{}", description)
+    } else {
+        write!(f, "\"{}\"", escape_json(description))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_value() {
+        for number in ["0", "-1", "12.5", "1e5", "-0.5E-3", "10"] {
+            assert!(is_json_number(number), "{}", number);
+        }
+        for other in ["", "-", "+5", ".5", "5.", "01", "1e", "1e+", "inf", "NaN", "0x10", "1 "] {
+            assert!(!is_json_number(other), "{}", other);
+        }
+
+        let mut buf = String::new();
+        write_json_value(&mut buf, "+5");
+        buf.push(',');
+        write_json_value(&mut buf, "-2.5");
+        assert_eq!(buf, "\"+5\",-2.5");
+    }
+}
@@ -0,0 +1,178 @@
+// Copyright 2025 KenForever1
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! 以Prometheus文本格式导出所有暴露的变量
+
+use std::collections::HashSet;
+use std::fmt::{self, Write};
+
+use crate::export::MetricSample;
+use crate::variable::{collect_exposed, DumpOptions};
+
+/// 把匹配`options`的变量以Prometheus文本格式写入`f`
+///
+/// 每个变量输出`# HELP`、`# TYPE`以及它的所有样本，`# HELP`的内容为变量原本的名称。
+/// 没有数值样本的变量会被跳过，转换后与前面的变量同名的变量也会被跳过
+pub fn write_metrics(f: &mut dyn Write, options: &DumpOptions) -> fmt::Result {
+    let mut families = HashSet::new();
+    for (original, var) in collect_exposed(options) {
+        let samples = var.metric_samples();
+        if samples.is_empty() {
+            continue;
+        }
+
+        let name = sanitize_name(&original);
+        if !families.insert(name.clone()) {
+            log::warn!("Skip {} whose prometheus name {} is already used", original, name);
+            continue;
+        }
+        writeln!(f, "# HELP {} {}", name, escape_help(&original))?;
+        writeln!(f, "# TYPE {} {}", name, var.metric_kind().name())?;
+        for sample in &samples {
+            write_sample(f, &name, sample)?;
+        }
+    }
+    Ok(())
+}
+
+/// 以Prometheus文本格式导出匹配`options`的变量
+pub fn render(options: &DumpOptions) -> String {
+    let mut buf = String::new();
+    let _ = write_metrics(&mut buf, options);
+    buf
+}
+
+/// 输出一行样本
+fn write_sample(f: &mut dyn Write, name: &str, sample: &MetricSample) -> fmt::Result {
+    write!(f, "{}{}", name, sanitize_name(&sample.suffix))?;
+    if !sample.labels.is_empty() {
+        write!(f, "{{")?;
+        for (i, (label, value)) in sample.labels.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}=\"{}\"", sanitize_name(label), escape_label_value(value))?;
+        }
+        write!(f, "}}")?;
+    }
+    writeln!(f, " {}", format_value(sample.value))
+}
+
+/// 把名称中不合法的字符替换为`_`，名称只能包含`[a-zA-Z0-9_:]`且不能以数字开头
+fn sanitize_name(name: &str) -> String {
+    let mut result = String::with_capacity(name.len() + 1);
+    for (i, c) in name.chars().enumerate() {
+        if i == 0 && c.is_ascii_digit() {
+            result.push('_');
+        }
+        if c.is_ascii_alphanumeric() || c == '_' || c == ':' {
+            result.push(c);
+        } else {
+            result.push('_');
+        }
+    }
+    result
+}
+
+/// 转义标签值中的`\`、`"`和换行
+fn escape_label_value(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => result.push_str("\\\\"),
+            '"' => result.push_str("\\\""),
+            '\n' => result.push_str("\\n"),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/// 转义帮助文本中的`\`和换行
+fn escape_help(help: &str) -> String {
+    help.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+/// 按照Prometheus的约定格式化数值
+fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recorder::IntRecorder;
+    use crate::reducer::{Adder, Maxer};
+    use crate::status::Status;
+    use crate::variable::Variable;
+
+    #[test]
+    fn test_render() {
+        let adder: Adder<i64> = Adder::with_name("prom_test_requests").unwrap();
+        adder.add(3);
+        let _maxer = Maxer::with_name(7, "prom_test_max_latency").unwrap();
+        let _version = Status::with_name("v1".to_string(), "prom_test_version").unwrap();
+        let _ratio = Status::with_name(0.5, "prom_test_ratio").unwrap();
+        let recorder = IntRecorder::with_name("prom_test_latency").unwrap();
+        recorder.add(10);
+        recorder.add(20);
+
+        let text = render(&DumpOptions::new().with_white_wildcards("prom_test_*"));
+        let expected = "\
+# HELP prom_test_latency prom_test_latency
+# TYPE prom_test_latency summary
+prom_test_latency_sum 30
+prom_test_latency_count 2
+# HELP prom_test_max_latency prom_test_max_latency
+# TYPE prom_test_max_latency gauge
+prom_test_max_latency 7
+# HELP prom_test_ratio prom_test_ratio
+# TYPE prom_test_ratio gauge
+prom_test_ratio 0.5
+# HELP prom_test_requests prom_test_requests
+# TYPE prom_test_requests counter
+prom_test_requests 3
+";
+        assert_eq!(text, expected);
+        assert!(adder.name() == "prom_test_requests");
+
+        // 数字开头的名称会加上`_`，与已有的变量同名时只输出第一个
+        let _digit = Status::with_name(1, "9prom_dup").unwrap();
+        let _underscored = Status::with_name(2, "_9prom_dup").unwrap();
+        let text = render(&DumpOptions::new().with_white_wildcards("*9prom_dup"));
+        assert_eq!(text, "# HELP _9prom_dup 9prom_dup\n# TYPE _9prom_dup gauge\n_9prom_dup 1\n");
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(sanitize_name("9lives.rate"), "_9lives_rate");
+        assert_eq!(sanitize_name("ok_name:sub"), "ok_name:sub");
+        assert_eq!(escape_label_value("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+        assert_eq!(escape_help("a\"b\\c\nd"), "a\"b\\\\c\\nd");
+        assert_eq!(format_value(f64::INFINITY), "+Inf");
+        assert_eq!(format_value(f64::NAN), "NaN");
+        assert_eq!(format_value(2.0), "2");
+
+        let mut buf = String::new();
+        let sample = MetricSample::new(1.0).with_label("method", "get\"x\"");
+        write_sample(&mut buf, "rpc", &sample).unwrap();
+        assert_eq!(buf, "rpc{method=\"get\\\"x\\\"\"} 1\n");
+    }
+}
@@ -0,0 +1,524 @@
+// Copyright 2025 KenForever1
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! 统计数值分布的直方图，可以导出为Prometheus的histogram
+
+use std::fmt;
+use std::fmt::Write;
+use std::sync::Arc;
+
+use crate::detail::combiner::{AgentCombiner, AgentModifier, AgentValue, Combiner, InverseOp, LockedElement};
+use crate::detail::sampler::SharedSampler;
+use crate::export::{MetricKind, MetricSample};
+use crate::reducer::ReducerTrait;
+use crate::variable::{ExposeError, Exposure, Variable};
+use crate::window::{Window, WindowSource};
+
+/// 直方图的桶边界
+///
+/// 保存每个桶的上界（包含），最后还有一个隐含的`+Inf`桶，落在`(bounds[i-1], bounds[i]]`
+/// 内的值计入第i个桶。克隆只复制引用。
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistogramBuckets {
+    /// 从小到大排列的上界
+    bounds: Arc<[f64]>,
+}
+
+impl HistogramBuckets {
+    /// 使用给定的上界，忽略非有限值并去重
+    pub fn explicit(mut bounds: Vec<f64>) -> Self {
+        bounds.retain(|bound| bound.is_finite());
+        bounds.sort_by(|a, b| a.total_cmp(b));
+        bounds.dedup();
+        Self {
+            bounds: bounds.into(),
+        }
+    }
+
+    /// 指数增长的上界：`start, start * factor, start * factor^2, ...`，共`count`个
+    pub fn exponential(start: f64, factor: f64, count: usize) -> Self {
+        assert!(start > 0.0, "start of exponential buckets must be positive");
+        assert!(factor > 1.0, "factor of exponential buckets must be greater than 1");
+        let bounds = (0..count)
+            .scan(start, |bound, _| {
+                let current = *bound;
+                *bound *= factor;
+                Some(current)
+            })
+            .collect();
+        Self::explicit(bounds)
+    }
+
+    /// 类似HDR Histogram的对数线性上界
+    ///
+    /// 从不大于`min`的2的幂开始，每个`[2^k, 2^(k+1))`区间再线性地分为`sub_buckets`份，
+    /// 直到覆盖`max`。相对误差不超过`1 / sub_buckets`。
+    pub fn log_linear(min: f64, max: f64, sub_buckets: usize) -> Self {
+        assert!(min > 0.0 && min <= max, "log-linear buckets need 0 < min <= max");
+        assert!(sub_buckets > 0, "sub_buckets must be positive");
+        let mut lower = 2f64.powi(min.log2().floor() as i32);
+        let mut bounds = vec![lower];
+        while lower < max {
+            let step = lower / sub_buckets as f64;
+            for i in 1..=sub_buckets {
+                bounds.push(lower + step * i as f64);
+            }
+            lower *= 2.0;
+        }
+        Self::explicit(bounds)
+    }
+
+    /// 有限的上界，不包含`+Inf`
+    pub fn bounds(&self) -> &[f64] {
+        &self.bounds
+    }
+
+    /// 桶的数量，包含`+Inf`桶
+    pub fn len(&self) -> usize {
+        self.bounds.len() + 1
+    }
+
+    /// 总是至少有`+Inf`一个桶
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// `value`所在的桶
+    pub fn bucket_index(&self, value: f64) -> usize {
+        self.bounds.partition_point(|bound| *bound < value)
+    }
+}
+
+/// 直方图在某一时刻的快照，可以合并，也可以减去较早的快照
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistogramSnapshot {
+    /// 桶边界
+    buckets: HistogramBuckets,
+    /// 每个桶内的样本数，不是累计值
+    counts: Vec<u64>,
+    /// 样本的总和
+    sum: f64,
+    /// 样本数
+    count: u64,
+}
+
+impl HistogramSnapshot {
+    /// 创建空的快照
+    pub fn new(buckets: HistogramBuckets) -> Self {
+        Self {
+            counts: vec![0; buckets.len()],
+            buckets,
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    /// 记录一个样本，NaN和无穷大会被丢弃，避免污染总和
+    pub fn record(&mut self, value: f64) {
+        if !value.is_finite() {
+            return;
+        }
+        self.counts[self.buckets.bucket_index(value)] += 1;
+        self.sum += value;
+        self.count += 1;
+    }
+
+    /// 合并另一个相同桶边界的快照
+    pub fn merge(&mut self, other: &HistogramSnapshot) {
+        assert_eq!(self.buckets, other.buckets, "cannot merge histograms with different buckets");
+        for (count, other_count) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other_count;
+        }
+        self.sum += other.sum;
+        self.count += other.count;
+    }
+
+    /// 减去较早的快照，得到两次快照之间的分布
+    pub fn subtract(&mut self, earlier: &HistogramSnapshot) {
+        assert_eq!(self.buckets, earlier.buckets, "cannot subtract histograms with different buckets");
+        for (count, earlier_count) in self.counts.iter_mut().zip(&earlier.counts) {
+            *count = count.saturating_sub(*earlier_count);
+        }
+        self.sum -= earlier.sum;
+        self.count = self.count.saturating_sub(earlier.count);
+    }
+
+    /// 桶边界
+    pub fn buckets(&self) -> &HistogramBuckets {
+        &self.buckets
+    }
+
+    /// 每个桶内的样本数，最后一个是`+Inf`桶
+    pub fn counts(&self) -> &[u64] {
+        &self.counts
+    }
+
+    /// 样本的总和
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    /// 样本数
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// 导出为Prometheus的`_bucket`、`_sum`和`_count`样本，`_bucket`是累计值
+    pub fn metric_samples(&self) -> Vec<MetricSample> {
+        let mut samples = Vec::with_capacity(self.counts.len() + 2);
+        let mut cumulative = 0;
+        for (i, count) in self.counts.iter().enumerate() {
+            cumulative += count;
+            samples.push(
+                MetricSample::new(cumulative as f64)
+                    .with_suffix("_bucket")
+                    .with_label("le", &self.bucket_label(i)),
+            );
+        }
+        samples.push(MetricSample::new(self.sum).with_suffix("_sum"));
+        samples.push(MetricSample::new(self.count as f64).with_suffix("_count"));
+        samples
+    }
+
+    /// 第`index`个桶的上界
+    fn bucket_label(&self, index: usize) -> String {
+        match self.buckets.bounds().get(index) {
+            Some(bound) => bound.to_string(),
+            None => "+Inf".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for HistogramSnapshot {
+    /// 输出样本数、总和以及每个桶的样本数，如`{"count":3,"sum":6,"buckets":{"1":1,"+Inf":2}}`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{{\"count\":{},\"sum\":{},\"buckets\":{{", self.count, self.sum)?;
+        for (i, count) in self.counts.iter().enumerate() {
+            if i > 0 {
+                f.write_char(',')?;
+            }
+            write!(f, "\"{}\":{}", self.bucket_label(i), count)?;
+        }
+        f.write_str("}}")
+    }
+}
+
+impl AgentValue for HistogramSnapshot {
+    type Container = LockedElement<HistogramSnapshot>;
+}
+
+/// 合并两个快照
+#[derive(Clone)]
+pub struct MergeSnapshots;
+
+impl Combiner<HistogramSnapshot> for MergeSnapshots {
+    fn combine(&self, mut v1: HistogramSnapshot, v2: HistogramSnapshot) -> HistogramSnapshot {
+        v1.merge(&v2);
+        v1
+    }
+
+    fn modify(&self, v: HistogramSnapshot) -> HistogramSnapshot {
+        v
+    }
+
+    fn name(&self) -> &str {
+        "merge"
+    }
+}
+
+/// [`MergeSnapshots`]的逆操作
+#[derive(Clone)]
+pub struct SubtractSnapshots;
+
+impl InverseOp<HistogramSnapshot> for SubtractSnapshots {
+    fn inverse(&self, mut v1: HistogramSnapshot, v2: HistogramSnapshot) -> HistogramSnapshot {
+        v1.subtract(&v2);
+        v1
+    }
+}
+
+/// 统计数值分布的直方图
+///
+/// `record`只修改当前线程的Agent，读取时合并所有线程的快照：
+///
+/// ```ignore
+/// let latency = Histogram::with_name("rpc_latency", HistogramBuckets::exponential(1.0, 2.0, 20))?;
+/// latency.record(elapsed_us as f64);
+/// let latency_10s = WindowedHistogram::with_name("rpc_latency_10s", &latency, 10)?;
+/// ```
+#[derive(Clone)]
+pub struct Histogram {
+    /// 合并各线程的快照
+    combiner: Arc<AgentCombiner<HistogramSnapshot, MergeSnapshots>>,
+    /// 桶边界
+    buckets: HistogramBuckets,
+    /// 窗口使用的采样器
+    sampler: SharedSampler,
+    /// 暴露信息
+    exposure: Exposure,
+}
+
+impl Histogram {
+    /// 创建使用`buckets`的直方图
+    pub fn new(buckets: HistogramBuckets) -> Self {
+        Self {
+            combiner: Arc::new(AgentCombiner::new(
+                HistogramSnapshot::new(buckets.clone()),
+                MergeSnapshots,
+                String::new(),
+            )),
+            buckets,
+            sampler: SharedSampler::new(),
+            exposure: Exposure::new(),
+        }
+    }
+
+    /// 用名称创建，名称不可用时返回错误
+    pub fn with_name(name: &str, buckets: HistogramBuckets) -> Result<Self, ExposeError> {
+        let histogram = Self::new(buckets);
+        histogram.expose(name)?;
+        Ok(histogram)
+    }
+
+    /// 记录一个样本，NaN和无穷大会被丢弃
+    pub fn record(&self, value: f64) -> &Self {
+        if !value.is_finite() {
+            return self;
+        }
+        let modifier = AgentModifier::new(|snapshot: &mut HistogramSnapshot, value: &f64| {
+            snapshot.record(*value)
+        });
+        self.combiner.add_with(&modifier, &value);
+        self
+    }
+
+    /// 获取所有线程合并后的快照
+    pub fn get_value(&self) -> HistogramSnapshot {
+        self.combiner.combine_agents()
+    }
+
+    /// 清空所有样本，返回清空前的快照
+    pub fn reset(&self) -> HistogramSnapshot {
+        self.combiner.reset_all_agents()
+    }
+
+    /// 桶边界
+    pub fn buckets(&self) -> &HistogramBuckets {
+        &self.buckets
+    }
+}
+
+impl Variable for Histogram {
+    fn describe(&self, f: &mut String, _quote_string: bool) -> bool {
+        let _ = write!(f, "{}", self.get_value());
+        true
+    }
+
+    fn expose_impl(&self, prefix: &str, name: &str) -> Result<(), ExposeError> {
+        self.default_expose_impl(prefix, name)
+    }
+
+    fn exposure(&self) -> Option<&Exposure> {
+        Some(&self.exposure)
+    }
+
+    fn metric_kind(&self) -> MetricKind {
+        MetricKind::Histogram
+    }
+
+    fn metric_samples(&self) -> Vec<MetricSample> {
+        self.get_value().metric_samples()
+    }
+}
+
+impl ReducerTrait<HistogramSnapshot, MergeSnapshots> for Histogram {
+    fn get_value(&self) -> HistogramSnapshot {
+        self.get_value()
+    }
+
+    fn reset(&self) -> HistogramSnapshot {
+        self.reset()
+    }
+
+    fn op(&self) -> MergeSnapshots {
+        MergeSnapshots
+    }
+}
+
+impl WindowSource for Histogram {
+    type Value = HistogramSnapshot;
+    type Op = MergeSnapshots;
+    type InvOp = SubtractSnapshots;
+
+    fn inv_op(&self) -> SubtractSnapshots {
+        SubtractSnapshots
+    }
+
+    fn shared_sampler(&self) -> &SharedSampler {
+        &self.sampler
+    }
+}
+
+/// 直方图在最近一段时间内的分布
+///
+/// 用最新的快照减去窗口起点的快照，导出时和[`Histogram`]一样是Prometheus的histogram
+#[derive(Clone)]
+pub struct WindowedHistogram {
+    /// 直方图上的窗口
+    window: Window<Histogram>,
+    /// 还没有样本时使用的空快照
+    empty: HistogramSnapshot,
+    /// 暴露信息
+    exposure: Exposure,
+}
+
+impl WindowedHistogram {
+    /// 创建统计最近`window_size`秒的窗口
+    pub fn new(histogram: &Histogram, window_size: u64) -> Self {
+        Self {
+            window: Window::new(histogram, window_size),
+            empty: HistogramSnapshot::new(histogram.buckets().clone()),
+            exposure: Exposure::new(),
+        }
+    }
+
+    /// 用名称创建，名称不可用时返回错误
+    pub fn with_name(name: &str, histogram: &Histogram, window_size: u64) -> Result<Self, ExposeError> {
+        let windowed = Self::new(histogram, window_size);
+        windowed.expose(name)?;
+        Ok(windowed)
+    }
+
+    /// 获取窗口内的分布，还没有样本时为空
+    pub fn get_value(&self) -> HistogramSnapshot {
+        self.window.get_value().unwrap_or_else(|| self.empty.clone())
+    }
+
+    /// 获取窗口大小（秒）
+    pub fn window_size(&self) -> u64 {
+        self.window.window_size()
+    }
+}
+
+impl Variable for WindowedHistogram {
+    fn describe(&self, f: &mut String, _quote_string: bool) -> bool {
+        let _ = write!(f, "{}", self.get_value());
+        true
+    }
+
+    fn expose_impl(&self, prefix: &str, name: &str) -> Result<(), ExposeError> {
+        self.default_expose_impl(prefix, name)
+    }
+
+    fn exposure(&self) -> Option<&Exposure> {
+        Some(&self.exposure)
+    }
+
+    fn metric_kind(&self) -> MetricKind {
+        MetricKind::Histogram
+    }
+
+    fn metric_samples(&self) -> Vec<MetricSample> {
+        self.get_value().metric_samples()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::detail::sampler::ReducerSampler;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_histogram_buckets() {
+        let explicit = HistogramBuckets::explicit(vec![5.0, 1.0, f64::NAN, 1.0, 10.0]);
+        assert_eq!(explicit.bounds(), &[1.0, 5.0, 10.0]);
+        assert_eq!(explicit.len(), 4);
+        assert_eq!(explicit.bucket_index(0.5), 0);
+        assert_eq!(explicit.bucket_index(1.0), 0);
+        assert_eq!(explicit.bucket_index(1.5), 1);
+        assert_eq!(explicit.bucket_index(11.0), 3);
+
+        let exponential = HistogramBuckets::exponential(1.0, 2.0, 4);
+        assert_eq!(exponential.bounds(), &[1.0, 2.0, 4.0, 8.0]);
+
+        let log_linear = HistogramBuckets::log_linear(3.0, 10.0, 4);
+        assert_eq!(
+            log_linear.bounds(),
+            &[2.0, 2.5, 3.0, 3.5, 4.0, 5.0, 6.0, 7.0, 8.0, 10.0, 12.0, 14.0, 16.0]
+        );
+    }
+
+    #[test]
+    fn test_histogram() {
+        let histogram = Histogram::new(HistogramBuckets::explicit(vec![1.0, 10.0]));
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let histogram = histogram.clone();
+                std::thread::spawn(move || {
+                    histogram.record(0.5).record(5.0).record(50.0);
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        histogram.record(1.0);
+        // 非有限值不计入任何桶，也不影响总和
+        histogram.record(f64::NAN).record(f64::INFINITY).record(f64::NEG_INFINITY);
+
+        let snapshot = histogram.get_value();
+        assert_eq!(snapshot.counts(), &[5, 4, 4]);
+        assert_eq!(snapshot.count(), 13);
+        assert_eq!(snapshot.sum(), 223.0);
+        assert_eq!(
+            histogram.get_description(),
+            r#"{"count":13,"sum":223,"buckets":{"1":5,"10":4,"+Inf":4}}"#
+        );
+
+        let samples = histogram.metric_samples();
+        let buckets: Vec<(String, f64)> = samples[..3]
+            .iter()
+            .map(|sample| (sample.labels[0].1.clone(), sample.value))
+            .collect();
+        assert_eq!(
+            buckets,
+            vec![("1".to_string(), 5.0), ("10".to_string(), 9.0), ("+Inf".to_string(), 13.0)]
+        );
+        assert_eq!(samples[3], MetricSample::new(223.0).with_suffix("_sum"));
+        assert_eq!(samples[4], MetricSample::new(13.0).with_suffix("_count"));
+
+        assert_eq!(histogram.reset().count(), 13);
+        assert_eq!(histogram.get_value().count(), 0);
+    }
+
+    #[test]
+    fn test_histogram_window() {
+        let start = Instant::now();
+        let at = |second: u64| start + Duration::from_secs(second);
+
+        let histogram = Histogram::new(HistogramBuckets::explicit(vec![1.0, 10.0]));
+        let sampler = ReducerSampler::new(&histogram, MergeSnapshots, SubtractSnapshots);
+        sampler.set_window_size(2);
+        for second in 0..4 {
+            histogram.record(second as f64 * 5.0);
+            sampler.take_sample_at(at(second));
+        }
+        // 减去两秒前的快照，只剩最近两秒记录的10和15
+        let windowed = sampler.get_value(2).unwrap();
+        assert_eq!(windowed.counts(), &[0, 1, 1]);
+        assert_eq!(windowed.sum(), 25.0);
+        assert_eq!(histogram.get_value().count(), 4);
+    }
+}
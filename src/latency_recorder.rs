@@ -0,0 +1,378 @@
+// Copyright 2025 KenForever1
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! 统计延时的组合变量，一次暴露平均延时、最大延时、QPS、总数和分位值
+
+use std::fmt::Write;
+use std::sync::Arc;
+
+use crate::detail::clock::Clock;
+use crate::export::MetricKind;
+use crate::percentile::Percentile;
+use crate::recorder::IntRecorder;
+use crate::reducer::Maxer;
+use crate::variable::{ExposeError, Exposure, Variable};
+use crate::window::{PerSecond, Window};
+
+/// 默认的窗口大小（秒）
+pub const DEFAULT_WINDOW_SIZE: u64 = 10;
+
+/// 暴露的分位值，依次为变量名的后缀和比例
+const PERCENTILES: [(&str, f64); 5] = [
+    ("50", 0.5),
+    ("90", 0.9),
+    ("99", 0.99),
+    ("999", 0.999),
+    ("9999", 0.9999),
+];
+
+/// 暴露窗口内的一个分位值
+#[derive(Clone)]
+struct PercentileVar {
+    percentile: Percentile,
+    ratio: f64,
+    exposure: Exposure,
+}
+
+impl Variable for PercentileVar {
+    fn describe(&self, f: &mut String, _quote_string: bool) -> bool {
+        let _ = write!(f, "{}", self.percentile.get_number(self.ratio));
+        true
+    }
+
+    fn expose_impl(&self, prefix: &str, name: &str) -> Result<(), ExposeError> {
+        self.default_expose_impl(prefix, name)
+    }
+
+    fn exposure(&self) -> Option<&Exposure> {
+        Some(&self.exposure)
+    }
+}
+
+/// 暴露记录过的样本总数
+#[derive(Clone)]
+struct CountVar {
+    recorder: IntRecorder,
+    exposure: Exposure,
+}
+
+impl Variable for CountVar {
+    fn describe(&self, f: &mut String, _quote_string: bool) -> bool {
+        let _ = write!(f, "{}", self.recorder.get_value().num);
+        true
+    }
+
+    fn expose_impl(&self, prefix: &str, name: &str) -> Result<(), ExposeError> {
+        self.default_expose_impl(prefix, name)
+    }
+
+    fn exposure(&self) -> Option<&Exposure> {
+        Some(&self.exposure)
+    }
+
+    fn metric_kind(&self) -> MetricKind {
+        MetricKind::Counter
+    }
+}
+
+/// 统计延时的组合变量，类似bvar的`LatencyRecorder`
+///
+/// 一次`expose("rpc_server")`会暴露以下变量，除了`_count`都是最近一个窗口内的值：
+///   - `rpc_server_latency`：平均延时
+///   - `rpc_server_max_latency`：最大延时
+///   - `rpc_server_qps`：每秒的样本数
+///   - `rpc_server_count`：样本总数
+///   - `rpc_server_latency_50/90/99/999/9999`：延时的分位值
+///
+/// ```ignore
+/// let latency = LatencyRecorder::with_name("rpc_server")?;
+/// latency.record(elapsed_us);
+/// ```
+#[derive(Clone)]
+pub struct LatencyRecorder {
+    /// 记录延时的总和与样本数
+    latency: IntRecorder,
+    /// 记录最大延时
+    max_latency: Maxer<i64>,
+    /// 记录分位值的样本
+    percentile: Percentile,
+    /// 窗口内的平均延时
+    latency_window: Window<IntRecorder>,
+    /// 窗口内的最大延时
+    max_latency_window: Window<Maxer<i64>>,
+    /// 窗口内的QPS
+    qps: PerSecond<IntRecorder>,
+    /// 样本总数
+    count: CountVar,
+    /// 窗口内的各个分位值
+    percentiles: Vec<PercentileVar>,
+}
+
+impl LatencyRecorder {
+    /// 创建统计最近10秒的延时记录器
+    pub fn new() -> Self {
+        Self::with_window_size(DEFAULT_WINDOW_SIZE)
+    }
+
+    /// 创建统计最近`window_size`秒的延时记录器
+    pub fn with_window_size(window_size: u64) -> Self {
+        let window_size = window_size.max(1);
+        let latency = IntRecorder::new();
+        let max_latency = Maxer::new(0);
+        Self::from_parts(
+            Window::new(&latency, window_size),
+            Window::new(&max_latency, window_size),
+            PerSecond::with_window_size(&latency, window_size),
+            Percentile::with_window_size(window_size),
+            latency,
+            max_latency,
+        )
+    }
+
+    /// 创建用`clock`计时的延时记录器，用于测试
+    ///
+    /// 不由全局采样线程驱动，需要调用[`LatencyRecorder::take_sample`]采样
+    pub fn with_clock(window_size: u64, clock: Arc<dyn Clock>) -> Self {
+        let window_size = window_size.max(1);
+        let latency = IntRecorder::new();
+        let max_latency = Maxer::new(0);
+        let recorder = Self::from_parts(
+            Window::with_clock(&latency, window_size, clock.clone()),
+            Window::with_clock(&max_latency, window_size, clock.clone()),
+            PerSecond::with_clock(&latency, window_size, clock),
+            Percentile::unscheduled(window_size),
+            latency,
+            max_latency,
+        );
+        // 与全局采样线程驱动的窗口一样，先记录一个样本作为起点
+        recorder.latency_window.take_sample();
+        recorder.max_latency_window.take_sample();
+        recorder
+    }
+
+    /// 用各个窗口组装延时记录器
+    fn from_parts(
+        latency_window: Window<IntRecorder>,
+        max_latency_window: Window<Maxer<i64>>,
+        qps: PerSecond<IntRecorder>,
+        percentile: Percentile,
+        latency: IntRecorder,
+        max_latency: Maxer<i64>,
+    ) -> Self {
+        Self {
+            latency_window,
+            max_latency_window,
+            qps,
+            count: CountVar {
+                recorder: latency.clone(),
+                exposure: Exposure::new(),
+            },
+            percentiles: PERCENTILES
+                .iter()
+                .map(|&(_, ratio)| PercentileVar {
+                    percentile: percentile.clone(),
+                    ratio,
+                    exposure: Exposure::new(),
+                })
+                .collect(),
+            latency,
+            max_latency,
+            percentile,
+        }
+    }
+
+    /// 用名称创建并暴露，名称不可用时返回错误
+    pub fn with_name(name: &str) -> Result<Self, ExposeError> {
+        let recorder = Self::new();
+        recorder.expose(name)?;
+        Ok(recorder)
+    }
+
+    /// 用前缀和名称创建并暴露，名称不可用时返回错误
+    pub fn with_prefix_name(prefix: &str, name: &str) -> Result<Self, ExposeError> {
+        let recorder = Self::new();
+        recorder.expose_as(prefix, name)?;
+        Ok(recorder)
+    }
+
+    /// 记录一次延时
+    pub fn record(&self, latency: i64) -> &Self {
+        self.latency.add(latency);
+        self.max_latency.add(latency);
+        self.percentile.add(latency);
+        self
+    }
+
+    /// 获取窗口内的平均延时
+    pub fn latency(&self) -> i64 {
+        self.latency_window
+            .get_value()
+            .map(|stat| stat.get_average_int())
+            .unwrap_or(0)
+    }
+
+    /// 获取窗口内的最大延时
+    pub fn max_latency(&self) -> i64 {
+        self.max_latency_window.get_value().unwrap_or(0)
+    }
+
+    /// 获取窗口内的QPS
+    pub fn qps(&self) -> f64 {
+        self.qps.get_value()
+    }
+
+    /// 获取记录过的样本总数
+    pub fn count(&self) -> i64 {
+        self.latency.get_value().num
+    }
+
+    /// 获取窗口内`ratio`分位的延时，`ratio`在0到1之间
+    pub fn latency_percentile(&self, ratio: f64) -> i64 {
+        self.percentile.get_number(ratio)
+    }
+
+    /// 立即对所有窗口采样一次，用于[`LatencyRecorder::with_clock`]创建的记录器
+    pub fn take_sample(&self) {
+        self.latency_window.take_sample();
+        self.max_latency_window.take_sample();
+        self.qps.take_sample();
+        self.percentile.take_sample();
+    }
+
+    /// 以`name`为前缀暴露所有变量
+    pub fn expose(&self, name: &str) -> Result<(), ExposeError> {
+        self.expose_as("", name)
+    }
+
+    /// 以`prefix`和`name`为前缀暴露所有变量，任何一个失败时已经暴露的变量会被隐藏
+    pub fn expose_as(&self, prefix: &str, name: &str) -> Result<(), ExposeError> {
+        self.hide();
+        let result = self.expose_all(prefix, name);
+        if result.is_err() {
+            self.hide();
+        }
+        result
+    }
+
+    /// 依次暴露所有变量
+    fn expose_all(&self, prefix: &str, name: &str) -> Result<(), ExposeError> {
+        self.latency_window
+            .expose_as(prefix, &format!("{}_latency", name))?;
+        self.max_latency_window
+            .expose_as(prefix, &format!("{}_max_latency", name))?;
+        self.qps.expose_as(prefix, &format!("{}_qps", name))?;
+        self.count.expose_as(prefix, &format!("{}_count", name))?;
+        for (var, (suffix, _)) in self.percentiles.iter().zip(PERCENTILES) {
+            var.expose_as(prefix, &format!("{}_latency_{}", name, suffix))?;
+        }
+        Ok(())
+    }
+
+    /// 隐藏所有变量，有任何变量被隐藏时返回true
+    pub fn hide(&self) -> bool {
+        let mut hidden = self.latency_window.hide();
+        hidden |= self.max_latency_window.hide();
+        hidden |= self.qps.hide();
+        hidden |= self.count.hide();
+        for var in &self.percentiles {
+            hidden |= var.hide();
+        }
+        hidden
+    }
+}
+
+impl Default for LatencyRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::detail::clock::MockClock;
+    use crate::variable::{describe_exposed, get_exposed, list_exposed};
+    use std::time::Duration;
+
+    #[test]
+    fn test_latency_recorder() {
+        let recorder = LatencyRecorder::new();
+        recorder.expose("latency_test_rpc").unwrap();
+        let names: Vec<String> = list_exposed()
+            .into_iter()
+            .filter(|name| name.starts_with("latency_test_rpc"))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "latency_test_rpc_count",
+                "latency_test_rpc_latency",
+                "latency_test_rpc_latency_50",
+                "latency_test_rpc_latency_90",
+                "latency_test_rpc_latency_99",
+                "latency_test_rpc_latency_999",
+                "latency_test_rpc_latency_9999",
+                "latency_test_rpc_max_latency",
+                "latency_test_rpc_qps",
+            ]
+        );
+
+        for latency in 1..=100 {
+            recorder.record(latency);
+        }
+        assert_eq!(recorder.count(), 100);
+        assert_eq!(recorder.latency_percentile(0.5), 50);
+        assert_eq!(recorder.latency_percentile(0.99), 99);
+        let mut value = String::new();
+        assert!(describe_exposed(
+            "latency_test_rpc_latency_90",
+            &mut value,
+            false
+        ));
+        assert_eq!(value, "90");
+
+        assert!(recorder.hide());
+        assert!(!recorder.hide());
+        assert!(get_exposed("latency_test_rpc_qps").is_none());
+    }
+
+    #[test]
+    fn test_latency_recorder_window() {
+        let clock = Arc::new(MockClock::new());
+        let recorder = LatencyRecorder::with_clock(2, clock.clone());
+        recorder.expose("latency_window_test_rpc").unwrap();
+        for latencies in [&[1000][..], &[10, 30], &[20]] {
+            for &latency in latencies {
+                recorder.record(latency);
+            }
+            clock.advance(Duration::from_secs(1));
+            recorder.take_sample();
+        }
+
+        // 最近2秒内的样本为10、30、20，第一秒的1000已经移出窗口
+        assert_eq!(recorder.latency(), 20);
+        assert_eq!(recorder.max_latency(), 30);
+        assert_eq!(recorder.qps(), 1.5);
+        assert_eq!(recorder.latency_percentile(1.0), 30);
+        assert_eq!(recorder.count(), 4);
+        let describe = |name: &str| {
+            let mut value = String::new();
+            assert!(describe_exposed(name, &mut value, false));
+            value
+        };
+        assert_eq!(describe("latency_window_test_rpc_latency"), "20");
+        assert_eq!(describe("latency_window_test_rpc_max_latency"), "30");
+        assert_eq!(describe("latency_window_test_rpc_qps"), "1.5");
+    }
+}
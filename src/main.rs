@@ -13,12 +13,18 @@
 // limitations under the License.
 use variable::Variable;
 pub mod detail;
+pub mod export;
 
 pub mod recorder;
 pub mod variable;
 pub mod status;
 pub mod window;
 pub mod reducer;
+pub mod server;
+pub mod latency_recorder;
+pub mod percentile;
+pub mod histogram;
+pub mod multi_dimension;
 
 fn main() {
     println!("Hello, world!");
@@ -42,12 +48,13 @@ fn main() {
 
     // 暴露变量
     let res = recorder.expose("test_recorder");
-    println!("expose结果: {}", res);
+    println!("expose结果: {:?}", res);
     println!("is_hidden (暴露后): {}", recorder.is_hidden());
     println!("变量名称: {}", recorder.name());
     
     // 创建另一个记录器，使用前缀
-    let recorder2 = recorder::IntRecorder::with_prefix_name("stats", "second_recorder");
+    let recorder2 = recorder::IntRecorder::with_prefix_name("stats", "second_recorder")
+        .expect("stats_second_recorder已经被暴露");
     recorder2.add(10);
     recorder2.add(20);
     println!("recorder2名称: {}", recorder2.name());
@@ -56,6 +63,13 @@ fn main() {
     // 查看暴露的变量数量
     let count = variable::count_exposed();
     println!("已暴露的变量数量: {}", count);
+
+    // 按名称读取暴露的变量
+    for name in variable::list_exposed() {
+        let mut value = String::new();
+        variable::describe_exposed(&name, &mut value, false);
+        println!("{} : {}", name, value);
+    }
     
     // 隐藏变量
     let hide_result = recorder.hide();
@@ -0,0 +1,303 @@
+// Copyright 2025 KenForever1
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! 多维度变量，类似bvar的`MultiDimension`，每组标签值对应一个内部变量
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+
+use crate::export::{escape_json, write_json_value, MetricKind, MetricSample};
+use crate::variable::{ExposeError, Exposure, Variable};
+
+/// 默认最多保存的标签组合数量，与bvar的`max_multi_dimension_stats_count`一致
+pub const DEFAULT_MAX_STATS_COUNT: usize = 20000;
+
+/// 所有克隆共享的状态
+struct Inner<V> {
+    /// 标签名
+    labels: Vec<String>,
+    /// 每组标签值对应的变量
+    stats: RwLock<BTreeMap<Vec<String>, V>>,
+    /// 创建内部变量
+    factory: Box<dyn Fn() -> V + Send + Sync>,
+    /// 最多保存的标签组合数量
+    max_stats_count: AtomicUsize,
+}
+
+/// 按一组固定的标签名统计的变量
+///
+/// 第一次用某组标签值调用`get_stats`时创建内部变量，内部变量不会单独暴露，
+/// 导出到Prometheus时每组标签值是一个带标签的样本：
+///
+/// ```ignore
+/// let requests: MultiDimension<Adder<i64>> = MultiDimension::new(&["method", "status"]);
+/// requests.expose("rpc_requests")?;
+/// requests.get_stats(&["get", "200"]).unwrap().add(1);
+/// // rpc_requests{method="get",status="200"} 1
+/// ```
+pub struct MultiDimension<V> {
+    /// 共享的状态
+    inner: Arc<Inner<V>>,
+    /// 暴露信息
+    exposure: Exposure,
+}
+
+impl<V> Clone for MultiDimension<V> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            exposure: self.exposure.clone(),
+        }
+    }
+}
+
+impl<V: Variable + Clone> MultiDimension<V> {
+    /// 用`V::default()`创建内部变量
+    pub fn new(labels: &[&str]) -> Self
+    where
+        V: Default,
+    {
+        Self::with_factory(labels, V::default)
+    }
+
+    /// 用`factory`创建内部变量
+    pub fn with_factory<F>(labels: &[&str], factory: F) -> Self
+    where
+        F: Fn() -> V + Send + Sync + 'static,
+    {
+        Self {
+            inner: Arc::new(Inner {
+                labels: labels.iter().map(|label| label.to_string()).collect(),
+                stats: RwLock::new(BTreeMap::new()),
+                factory: Box::new(factory),
+                max_stats_count: AtomicUsize::new(DEFAULT_MAX_STATS_COUNT),
+            }),
+            exposure: Exposure::new(),
+        }
+    }
+
+    /// 用名称创建，名称不可用时返回错误
+    pub fn with_name(name: &str, labels: &[&str]) -> Result<Self, ExposeError>
+    where
+        V: Default,
+    {
+        let multi = Self::new(labels);
+        multi.expose(name)?;
+        Ok(multi)
+    }
+
+    /// 设置最多保存的标签组合数量
+    pub fn with_max_stats_count(self, max_stats_count: usize) -> Self {
+        self.set_max_stats_count(max_stats_count);
+        self
+    }
+
+    /// 修改最多保存的标签组合数量，已经创建的变量不受影响
+    pub fn set_max_stats_count(&self, max_stats_count: usize) {
+        self.inner.max_stats_count.store(max_stats_count, Ordering::Relaxed);
+    }
+
+    /// 标签名
+    pub fn labels(&self) -> &[String] {
+        &self.inner.labels
+    }
+
+    /// 获取一组标签值对应的变量，不存在时创建
+    ///
+    /// 标签值的数量和标签名不一致，或者标签组合的数量已经达到上限时返回`None`
+    pub fn get_stats(&self, label_values: &[&str]) -> Option<V> {
+        if label_values.len() != self.inner.labels.len() {
+            return None;
+        }
+        let key: Vec<String> = label_values.iter().map(|value| value.to_string()).collect();
+        if let Some(stats) = self.inner.stats.read().get(&key) {
+            return Some(stats.clone());
+        }
+
+        let mut stats = self.inner.stats.write();
+        if let Some(existing) = stats.get(&key) {
+            return Some(existing.clone());
+        }
+        if stats.len() >= self.inner.max_stats_count.load(Ordering::Relaxed) {
+            log::warn!(
+                "too many stats in multi-dimension variable `{}`, max is {}",
+                self.name(),
+                self.inner.max_stats_count.load(Ordering::Relaxed)
+            );
+            return None;
+        }
+        let created = (self.inner.factory)();
+        stats.insert(key, created.clone());
+        Some(created)
+    }
+
+    /// 是否存在一组标签值对应的变量
+    pub fn has_stats(&self, label_values: &[&str]) -> bool {
+        let key: Vec<String> = label_values.iter().map(|value| value.to_string()).collect();
+        self.inner.stats.read().contains_key(&key)
+    }
+
+    /// 删除一组标签值对应的变量，存在时返回true
+    pub fn delete_stats(&self, label_values: &[&str]) -> bool {
+        let key: Vec<String> = label_values.iter().map(|value| value.to_string()).collect();
+        self.inner.stats.write().remove(&key).is_some()
+    }
+
+    /// 删除所有变量
+    pub fn clear_stats(&self) {
+        self.inner.stats.write().clear();
+    }
+
+    /// 标签组合的数量
+    pub fn count_stats(&self) -> usize {
+        self.inner.stats.read().len()
+    }
+
+    /// 所有标签组合，按标签值排序
+    pub fn list_stats(&self) -> Vec<Vec<String>> {
+        self.inner.stats.read().keys().cloned().collect()
+    }
+
+    /// 按标签值的顺序复制所有变量，避免持有锁时调用内部变量
+    fn snapshot(&self) -> Vec<(Vec<String>, V)> {
+        self.inner
+            .stats
+            .read()
+            .iter()
+            .map(|(key, stats)| (key.clone(), stats.clone()))
+            .collect()
+    }
+}
+
+impl<V: Variable + Clone> Variable for MultiDimension<V> {
+    /// 输出JSON数组，每个元素包含标签值和内部变量的描述
+    fn describe(&self, f: &mut String, _quote_string: bool) -> bool {
+        f.push('[');
+        for (i, (key, stats)) in self.snapshot().iter().enumerate() {
+            if i > 0 {
+                f.push(',');
+            }
+            f.push('{');
+            for (label, value) in self.inner.labels.iter().zip(key) {
+                let _ = write!(f, "\"{}\":\"{}\",", escape_json(label), escape_json(value));
+            }
+            f.push_str("\"value\":");
+            write_json_value(f, &stats.get_description());
+            f.push('}');
+        }
+        f.push(']');
+        true
+    }
+
+    fn expose_impl(&self, prefix: &str, name: &str) -> Result<(), ExposeError> {
+        self.default_expose_impl(prefix, name)
+    }
+
+    fn exposure(&self) -> Option<&Exposure> {
+        Some(&self.exposure)
+    }
+
+    /// 与内部变量一致，还没有内部变量时为gauge
+    fn metric_kind(&self) -> MetricKind {
+        self.inner
+            .stats
+            .read()
+            .values()
+            .next()
+            .map(Variable::metric_kind)
+            .unwrap_or(MetricKind::Gauge)
+    }
+
+    /// 内部变量的样本加上标签，标签在内部变量自己的标签之前
+    fn metric_samples(&self) -> Vec<MetricSample> {
+        let mut samples = Vec::new();
+        for (key, stats) in self.snapshot() {
+            for mut sample in stats.metric_samples() {
+                let mut labels: Vec<(String, String)> =
+                    self.inner.labels.iter().cloned().zip(key.iter().cloned()).collect();
+                labels.append(&mut sample.labels);
+                sample.labels = labels;
+                samples.push(sample);
+            }
+        }
+        samples
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::prometheus::render;
+    use crate::recorder::IntRecorder;
+    use crate::reducer::Adder;
+    use crate::variable::DumpOptions;
+
+    #[test]
+    fn test_multi_dimension() {
+        let requests: MultiDimension<Adder<i64>> =
+            MultiDimension::new(&["method", "status"]).with_max_stats_count(3);
+        assert!(requests.get_stats(&["get"]).is_none());
+        requests.get_stats(&["get", "200"]).unwrap().add(2);
+        requests.get_stats(&["get", "200"]).unwrap().add(1);
+        requests.get_stats(&["post", "500"]).unwrap().add(1);
+        requests.get_stats(&["get", "404"]).unwrap();
+        // 达到上限后不再创建新的标签组合
+        assert!(requests.get_stats(&["put", "200"]).is_none());
+        assert_eq!(requests.count_stats(), 3);
+        assert_eq!(requests.get_stats(&["get", "200"]).unwrap().get_value(), 3);
+
+        assert!(requests.delete_stats(&["get", "404"]));
+        assert!(!requests.has_stats(&["get", "404"]));
+        assert!(requests.get_stats(&["put", "200"]).is_some());
+        assert_eq!(
+            requests.list_stats(),
+            vec![vec!["get", "200"], vec!["post", "500"], vec!["put", "200"]]
+        );
+        assert_eq!(
+            requests.get_description(),
+            r#"[{"method":"get","status":"200","value":3},{"method":"post","status":"500","value":1},{"method":"put","status":"200","value":0}]"#
+        );
+
+        requests.clear_stats();
+        assert_eq!(requests.count_stats(), 0);
+    }
+
+    #[test]
+    fn test_multi_dimension_prometheus() {
+        let requests: MultiDimension<Adder<i64>> =
+            MultiDimension::with_name("multi_test_requests", &["method"]).unwrap();
+        requests.get_stats(&["get"]).unwrap().add(3);
+        requests.get_stats(&["post"]).unwrap().add(1);
+        let latency = MultiDimension::with_factory(&["method"], IntRecorder::new);
+        latency.expose("multi_test_latency").unwrap();
+        latency.get_stats(&["get"]).unwrap().add(10).add(20);
+
+        let text = render(&DumpOptions::new().with_white_wildcards("multi_test_*"));
+        let expected = "\
+# HELP multi_test_latency multi_test_latency
+# TYPE multi_test_latency summary
+multi_test_latency_sum{method=\"get\"} 30
+multi_test_latency_count{method=\"get\"} 2
+# HELP multi_test_requests multi_test_requests
+# TYPE multi_test_requests counter
+multi_test_requests{method=\"get\"} 3
+multi_test_requests{method=\"post\"} 1
+";
+        assert_eq!(text, expected);
+    }
+}
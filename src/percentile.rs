@@ -0,0 +1,490 @@
+// Copyright 2025 KenForever1
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! 估计分位值，类似bvar的`Percentile`
+//!
+//! 样本按`[2^i, 2^(i+1))`分到32个区间中，每个区间只按均匀概率保留有限个样本。
+//! 每个线程只写自己的样本，采样线程每秒把所有线程的样本合并为一组，
+//! 读取时再合并最近一个窗口内的样本。
+
+use std::fmt::Write;
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+use parking_lot::Mutex;
+
+use crate::detail::bounded_queue::BoundedQueue;
+use crate::detail::combiner::{AgentCombiner, AgentModifier, AgentValue, Combiner, LockedElement};
+use crate::detail::fast_rand::fast_rand_less_than;
+use crate::detail::sampler::{Sampler, GLOBAL_SAMPLER_STATE};
+use crate::export::{MetricKind, MetricSample};
+use crate::variable::{ExposeError, Exposure, Variable};
+use crate::window::WINDOW_SIZE_SECOND;
+
+/// 区间的数量，覆盖u32的所有取值
+pub const NUM_INTERVALS: usize = 32;
+/// 每个线程的每个区间最多保留的样本数
+pub const THREAD_SAMPLE_SIZE: usize = 254;
+/// 读取时合并窗口内的样本，每个区间最多保留的样本数
+pub const GLOBAL_SAMPLE_SIZE: usize = 1022;
+
+/// 描述中输出的分位值
+const DESCRIBED_RATIOS: [(&str, f64); 5] = [
+    ("0.5", 0.5),
+    ("0.9", 0.9),
+    ("0.99", 0.99),
+    ("0.999", 0.999),
+    ("0.9999", 0.9999),
+];
+
+/// 一个区间内按均匀概率保留的样本，类似bvar的`PercentileInterval`
+#[derive(Debug, Clone, Default)]
+pub struct PercentileInterval {
+    /// 保留的样本
+    samples: Vec<u32>,
+    /// 落入这个区间的样本总数
+    num_added: u64,
+    /// `samples`是否已经排序
+    sorted: bool,
+}
+
+impl PercentileInterval {
+    /// 加入一个样本，超过`capacity`后新样本以`capacity / num_added`的概率替换已有的样本
+    pub fn add(&mut self, value: u32, capacity: usize) {
+        self.num_added += 1;
+        self.sorted = false;
+        if self.samples.len() < capacity {
+            self.samples.push(value);
+        } else {
+            let index = fast_rand_less_than(self.num_added) as usize;
+            if index < self.samples.len() {
+                self.samples[index] = value;
+            }
+        }
+    }
+
+    /// 合并另一个区间，按双方代表的样本数量的比例保留样本
+    pub fn merge(&mut self, other: &PercentileInterval, capacity: usize) {
+        if other.num_added == 0 {
+            return;
+        }
+        self.sorted = false;
+        if self.samples.len() + other.samples.len() <= capacity {
+            self.samples.extend_from_slice(&other.samples);
+            self.num_added += other.num_added;
+            return;
+        }
+
+        let total = self.num_added + other.num_added;
+        let from_other = ((capacity as u128 * other.num_added as u128 / total as u128) as usize)
+            .min(other.samples.len());
+        let from_self = (capacity - from_other).min(self.samples.len());
+        choose_randomly(&mut self.samples, from_self);
+        let mut chosen = other.samples.clone();
+        choose_randomly(&mut chosen, from_other);
+        self.samples.append(&mut chosen);
+        self.num_added = total;
+    }
+
+    /// 落入这个区间的样本总数
+    pub fn added_count(&self) -> u64 {
+        self.num_added
+    }
+
+    /// 保留的样本数
+    pub fn sample_count(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// 获取从小到大第`index`个保留的样本
+    fn get_sample_at(&mut self, index: usize) -> u32 {
+        if !self.sorted {
+            self.samples.sort_unstable();
+            self.sorted = true;
+        }
+        self.samples[index.min(self.samples.len() - 1)]
+    }
+}
+
+/// 随机保留`samples`中的`count`个元素
+fn choose_randomly(samples: &mut Vec<u32>, count: usize) {
+    for i in 0..count {
+        let j = i + fast_rand_less_than((samples.len() - i) as u64) as usize;
+        samples.swap(i, j);
+    }
+    samples.truncate(count);
+}
+
+/// 分布在32个区间中的样本，类似bvar的`PercentileSamples`
+#[derive(Debug, Clone)]
+pub struct PercentileSamples {
+    /// 每个区间最多保留的样本数
+    capacity: usize,
+    /// 样本总数
+    num_added: u64,
+    /// 第i个区间保存`[2^i, 2^(i+1))`内的样本，第0个区间还包含0
+    intervals: Vec<PercentileInterval>,
+}
+
+impl PercentileSamples {
+    /// 创建每个区间最多保留`capacity`个样本的空集合
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            num_added: 0,
+            intervals: vec![PercentileInterval::default(); NUM_INTERVALS],
+        }
+    }
+
+    /// 样本所在的区间
+    fn interval_index(value: u32) -> usize {
+        if value < 2 {
+            0
+        } else {
+            31 - value.leading_zeros() as usize
+        }
+    }
+
+    /// 加入一个样本
+    pub fn add(&mut self, value: u32) {
+        self.intervals[Self::interval_index(value)].add(value, self.capacity);
+        self.num_added += 1;
+    }
+
+    /// 合并另一组样本
+    pub fn merge(&mut self, other: &PercentileSamples) {
+        for (interval, other_interval) in self.intervals.iter_mut().zip(&other.intervals) {
+            interval.merge(other_interval, self.capacity);
+        }
+        self.num_added += other.num_added;
+    }
+
+    /// 样本总数
+    pub fn count(&self) -> u64 {
+        self.num_added
+    }
+
+    /// 估计`ratio`分位的值，即从小到大第`ceil(ratio * count)`个样本，没有样本时返回0
+    pub fn get_number(&mut self, ratio: f64) -> u32 {
+        if self.num_added == 0 {
+            return 0;
+        }
+        let ratio = ratio.clamp(0.0, 1.0);
+        let mut rank = ((ratio * self.num_added as f64).ceil() as u64).clamp(1, self.num_added);
+        for interval in self.intervals.iter_mut() {
+            let added = interval.added_count();
+            if rank <= added {
+                // 按比例换算为区间内保留的样本的下标
+                let index = (rank - 1) as u128 * interval.sample_count() as u128 / added as u128;
+                return interval.get_sample_at(index as usize);
+            }
+            rank -= added;
+        }
+        0
+    }
+}
+
+impl Default for PercentileSamples {
+    fn default() -> Self {
+        Self::new(THREAD_SAMPLE_SIZE)
+    }
+}
+
+impl AgentValue for PercentileSamples {
+    type Container = LockedElement<PercentileSamples>;
+}
+
+/// 合并两组样本，合并结果每个区间最多保留[`GLOBAL_SAMPLE_SIZE`]个样本
+#[derive(Clone)]
+pub struct MergeSamples;
+
+impl Combiner<PercentileSamples> for MergeSamples {
+    fn combine(&self, mut v1: PercentileSamples, v2: PercentileSamples) -> PercentileSamples {
+        v1.capacity = v1.capacity.max(GLOBAL_SAMPLE_SIZE);
+        v1.merge(&v2);
+        v1
+    }
+
+    fn modify(&self, v: PercentileSamples) -> PercentileSamples {
+        v
+    }
+
+    fn name(&self) -> &str {
+        "merge"
+    }
+}
+
+/// 每秒把所有线程的样本合并为一组，保存最近一个窗口内每秒的样本
+struct PercentileSampler {
+    /// 各线程正在收集的样本
+    combiner: AgentCombiner<PercentileSamples, MergeSamples>,
+    /// 最近每秒的样本
+    windows: Mutex<BoundedQueue<PercentileSamples>>,
+}
+
+impl PercentileSampler {
+    /// 合并最近`window_size`秒的样本以及还没有被采样的样本
+    fn merged(&self, window_size: usize) -> PercentileSamples {
+        // 持有窗口的锁再合并各线程的样本，避免和采样交错时漏掉刚被取走的样本
+        let windows = self.windows.lock();
+        let mut merged = PercentileSamples::new(GLOBAL_SAMPLE_SIZE);
+        merged.merge(&self.combiner.combine_agents());
+        for samples in windows.iter().rev().take(window_size) {
+            merged.merge(samples);
+        }
+        merged
+    }
+}
+
+impl Sampler for PercentileSampler {
+    fn interval(&self) -> Duration {
+        Duration::from_secs(1)
+    }
+
+    fn take_sample(&self) {
+        let mut windows = self.windows.lock();
+        let samples = self.combiner.reset_all_agents();
+        windows.elim_push(samples);
+    }
+
+    fn describe(&self, f: &mut dyn std::fmt::Write) {
+        let _ = write!(f, "{} windows", self.windows.lock().len());
+    }
+
+    fn destroy(&self) {
+        self.windows.lock().clear();
+    }
+}
+
+/// 统计最近一段时间内的分位值
+///
+/// `add`只修改当前线程的样本，不需要全局锁。读取时合并最近`window_size`秒的样本，
+/// 以及当前这一秒还没有被采样的样本：
+///
+/// ```ignore
+/// let latency = Percentile::with_window_size(10);
+/// latency.add(120);
+/// let p99 = latency.get_number(0.99);
+/// let p99_in_5s = latency.get_number_in(0.99, 5);
+/// ```
+#[derive(Clone)]
+pub struct Percentile {
+    /// 采样器，由全局采样线程驱动
+    sampler: Arc<PercentileSampler>,
+    /// 窗口大小（秒）
+    window_size: u64,
+    /// 暴露信息
+    exposure: Exposure,
+}
+
+impl Percentile {
+    /// 创建统计最近60秒的分位值统计器
+    pub fn new() -> Self {
+        Self::with_window_size(WINDOW_SIZE_SECOND)
+    }
+
+    /// 创建统计最近`window_size`秒的分位值统计器
+    pub fn with_window_size(window_size: u64) -> Self {
+        let percentile = Self::unscheduled(window_size);
+        let weak: Weak<dyn Sampler> =
+            Arc::downgrade(&percentile.sampler) as Weak<PercentileSampler>;
+        GLOBAL_SAMPLER_STATE.lock().register_sampler(weak);
+        percentile
+    }
+
+    /// 创建不由全局采样线程驱动的统计器，用于测试
+    ///
+    /// 需要调用[`Percentile::take_sample`]把当前这一秒的样本放入窗口
+    pub fn unscheduled(window_size: u64) -> Self {
+        let window_size = window_size.max(1);
+        Self {
+            sampler: Arc::new(PercentileSampler {
+                combiner: AgentCombiner::new(PercentileSamples::default(), MergeSamples, String::new()),
+                windows: Mutex::new(BoundedQueue::new(window_size as usize)),
+            }),
+            window_size,
+            exposure: Exposure::new(),
+        }
+    }
+
+    /// 立即把各线程收集的样本放入窗口，开始新的一秒
+    pub fn take_sample(&self) {
+        self.sampler.take_sample();
+    }
+
+    /// 用名称创建，名称不可用时返回错误
+    pub fn with_name(name: &str) -> Result<Self, ExposeError> {
+        let percentile = Self::new();
+        percentile.expose(name)?;
+        Ok(percentile)
+    }
+
+    /// 加入一个样本，超出`[0, u32::MAX]`的样本会被截断
+    pub fn add(&self, value: i64) -> &Self {
+        let value = value.clamp(0, u32::MAX as i64) as u32;
+        let modifier = AgentModifier::new(|samples: &mut PercentileSamples, value: &u32| {
+            samples.add(*value)
+        });
+        self.sampler.combiner.add_with(&modifier, &value);
+        self
+    }
+
+    /// 获取窗口内`ratio`分位的值，`ratio`在0到1之间
+    pub fn get_number(&self, ratio: f64) -> i64 {
+        self.get_number_in(ratio, self.window_size)
+    }
+
+    /// 获取最近`window_size`秒内`ratio`分位的值，`window_size`会被限制在1到创建时的窗口大小之间
+    pub fn get_number_in(&self, ratio: f64, window_size: u64) -> i64 {
+        let window_size = window_size.clamp(1, self.window_size);
+        self.sampler.merged(window_size as usize).get_number(ratio) as i64
+    }
+
+    /// 获取窗口内合并后的样本
+    pub fn get_value(&self) -> PercentileSamples {
+        self.sampler.merged(self.window_size as usize)
+    }
+
+    /// 获取窗口大小（秒）
+    pub fn window_size(&self) -> u64 {
+        self.window_size
+    }
+}
+
+impl Default for Percentile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Variable for Percentile {
+    /// 输出50、90、99、99.9和99.99分位的值
+    fn describe(&self, f: &mut String, _quote_string: bool) -> bool {
+        let mut samples = self.get_value();
+        f.push('[');
+        for (i, (_, ratio)) in DESCRIBED_RATIOS.iter().enumerate() {
+            if i > 0 {
+                f.push(',');
+            }
+            let _ = write!(f, "{}", samples.get_number(*ratio));
+        }
+        f.push(']');
+        true
+    }
+
+    fn expose_impl(&self, prefix: &str, name: &str) -> Result<(), ExposeError> {
+        self.default_expose_impl(prefix, name)
+    }
+
+    fn exposure(&self) -> Option<&Exposure> {
+        Some(&self.exposure)
+    }
+
+    fn metric_kind(&self) -> MetricKind {
+        MetricKind::Summary
+    }
+
+    fn metric_samples(&self) -> Vec<MetricSample> {
+        let mut samples = self.get_value();
+        let mut result: Vec<MetricSample> = DESCRIBED_RATIOS
+            .iter()
+            .map(|(quantile, ratio)| {
+                MetricSample::new(samples.get_number(*ratio) as f64).with_label("quantile", quantile)
+            })
+            .collect();
+        result.push(MetricSample::new(samples.count() as f64).with_suffix("_count"));
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentile_samples() {
+        let mut samples = PercentileSamples::new(GLOBAL_SAMPLE_SIZE);
+        assert_eq!(samples.get_number(0.5), 0);
+        for value in 1..=1000 {
+            samples.add(value);
+        }
+        assert_eq!(PercentileSamples::interval_index(0), 0);
+        assert_eq!(PercentileSamples::interval_index(1023), 9);
+        assert_eq!(samples.count(), 1000);
+        // 每个区间都没有超过容量，结果是精确的
+        assert_eq!(samples.get_number(0.5), 500);
+        assert_eq!(samples.get_number(0.999), 999);
+        assert_eq!(samples.get_number(1.0), 1000);
+        assert_eq!(samples.get_number(0.0), 1);
+
+        // 超过容量后只保留部分样本，分位值仍然接近真实值
+        let mut large = PercentileSamples::default();
+        for value in 0..100_000 {
+            large.add(value);
+        }
+        let p90 = large.get_number(0.9);
+        assert!((85_000..95_000).contains(&p90), "{}", p90);
+
+        let mut merged = PercentileSamples::new(GLOBAL_SAMPLE_SIZE);
+        merged.merge(&samples);
+        merged.merge(&large);
+        assert_eq!(merged.count(), 101_000);
+        let p50 = merged.get_number(0.5);
+        assert!((45_000..55_000).contains(&p50), "{}", p50);
+    }
+
+    #[test]
+    fn test_percentile_window() {
+        let percentile = Percentile::unscheduled(3);
+        let threads: Vec<_> = (0..4)
+            .map(|i| {
+                let percentile = percentile.clone();
+                std::thread::spawn(move || {
+                    for value in 0..250 {
+                        percentile.add(i * 250 + value + 1);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        // 合并各线程的样本，还没有被采样的样本也能读到
+        assert_eq!(percentile.get_number(0.5), 500);
+        percentile.take_sample();
+        assert_eq!(percentile.get_number(0.99), 990);
+
+        // 后面几秒只有较大的值，较短的窗口只能看到它们
+        for _ in 0..2 {
+            for _ in 0..1000 {
+                percentile.add(100_000);
+            }
+            percentile.take_sample();
+        }
+        assert_eq!(percentile.get_number_in(0.5, 2), 100_000);
+        assert_eq!(percentile.get_number(0.2), 600);
+        // 超过创建时的窗口大小按窗口大小计算
+        assert_eq!(percentile.get_number_in(0.2, 100), 600);
+        percentile.take_sample();
+        percentile.take_sample();
+        assert_eq!(percentile.get_number(0.2), 100_000);
+
+        let mut description = String::new();
+        percentile.describe(&mut description, false);
+        assert_eq!(description, "[100000,100000,100000,100000,100000]");
+        let samples = percentile.metric_samples();
+        assert_eq!(samples[0].labels, vec![("quantile".to_string(), "0.5".to_string())]);
+        assert_eq!(samples[5].value, 1000.0);
+    }
+}
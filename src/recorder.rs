@@ -12,152 +12,470 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-//! 用于计算数值的平均值
+//! 用于计算数值的平均值、最值和标准差
 
 use std::fmt;
-use thread_local::ThreadLocal;
-use parking_lot::Mutex;
-use crate::variable::Variable;
+use std::sync::Arc;
+use parking_lot::RwLock;
+use crate::detail::combiner::{AgentCombiner, AgentValue, LockedElement};
+use crate::export::{is_json_number, MetricKind, MetricSample};
+use crate::detail::sampler::{SeriesSampler, SharedSampler, SharedSeries};
+use crate::detail::series::Series;
+use crate::reducer::{AddTo, MinusFrom, ReducerTrait, SumCombiner};
+use crate::window::WindowSource;
+use crate::variable::{ExposeError, Exposure, SeriesOptions, Variable};
 use std::fmt::Write;
-use std::cell::UnsafeCell;
-/// 统计结构，用于计算平均值
-#[derive(Debug, Clone, Default)]
-pub struct Stat {
+
+/// 可以被[`Recorder`]记录的样本类型
+pub trait StatValue:
+    Copy + PartialOrd + Default + fmt::Display + fmt::Debug + Send + Sync + 'static
+{
+    /// 没有样本时的最小值，大于等于任何样本
+    const UPPER: Self;
+    /// 没有样本时的最大值，小于等于任何样本
+    const LOWER: Self;
+    /// 新建的[`Recorder`]使用的输出方式
+    const DEFAULT_DISPLAY: RecorderDisplay;
+
+    /// 相加，溢出时返回`None`
+    fn checked_add(self, rhs: Self) -> Option<Self>;
+
+    /// 相加，溢出时取边界值
+    fn saturating_add(self, rhs: Self) -> Self;
+
+    /// 相减，用于窗口去掉较早的部分
+    fn wrapping_sub(self, rhs: Self) -> Self;
+
+    /// 减半，用于溢出时缩小总和
+    fn halve(self) -> Self;
+
+    /// 除以样本数
+    fn div_num(self, num: i64) -> Self;
+
+    /// 转换为浮点数
+    fn to_f64(self) -> f64;
+
+    /// 转换为整数，浮点数会被截断
+    fn to_i64(self) -> i64;
+}
+
+macro_rules! impl_int_stat_value {
+    ($($t:ty),*) => {
+        $(
+            impl StatValue for $t {
+                const UPPER: Self = <$t>::MAX;
+                const LOWER: Self = <$t>::MIN;
+                const DEFAULT_DISPLAY: RecorderDisplay = RecorderDisplay::IntAverage;
+
+                fn checked_add(self, rhs: Self) -> Option<Self> {
+                    <$t>::checked_add(self, rhs)
+                }
+
+                fn saturating_add(self, rhs: Self) -> Self {
+                    <$t>::saturating_add(self, rhs)
+                }
+
+                fn wrapping_sub(self, rhs: Self) -> Self {
+                    <$t>::wrapping_sub(self, rhs)
+                }
+
+                fn halve(self) -> Self {
+                    self / 2
+                }
+
+                fn div_num(self, num: i64) -> Self {
+                    (self as i128 / num as i128) as $t
+                }
+
+                fn to_f64(self) -> f64 {
+                    self as f64
+                }
+
+                fn to_i64(self) -> i64 {
+                    self as i64
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_float_stat_value {
+    ($($t:ty),*) => {
+        $(
+            impl StatValue for $t {
+                const UPPER: Self = <$t>::INFINITY;
+                const LOWER: Self = <$t>::NEG_INFINITY;
+                const DEFAULT_DISPLAY: RecorderDisplay = RecorderDisplay::FloatAverage(3);
+
+                fn checked_add(self, rhs: Self) -> Option<Self> {
+                    let sum = self + rhs;
+                    if sum.is_finite() || !self.is_finite() || !rhs.is_finite() {
+                        Some(sum)
+                    } else {
+                        None
+                    }
+                }
+
+                fn saturating_add(self, rhs: Self) -> Self {
+                    (self + rhs).clamp(<$t>::MIN, <$t>::MAX)
+                }
+
+                fn wrapping_sub(self, rhs: Self) -> Self {
+                    self - rhs
+                }
+
+                fn halve(self) -> Self {
+                    self / 2.0
+                }
+
+                fn div_num(self, num: i64) -> Self {
+                    self / num as $t
+                }
+
+                fn to_f64(self) -> f64 {
+                    self as f64
+                }
+
+                fn to_i64(self) -> i64 {
+                    self as i64
+                }
+            }
+        )*
+    };
+}
+
+impl_int_stat_value!(i32, i64, u32, u64);
+impl_float_stat_value!(f32, f64);
+
+/// 统计结构，用于计算平均值、最值和方差
+///
+/// 总和快要溢出时，类似bvar的做法把`sum`和`num`同时减半，平均值基本不变，
+/// 但`num`不再是样本的准确数量。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stat<T = i64> {
     /// 值的总和
-    pub sum: i64,
+    pub sum: T,
     /// 值的数量
     pub num: i64,
+    /// 最小值，没有样本或者未知时为[`StatValue::UPPER`]
+    pub min: T,
+    /// 最大值，没有样本或者未知时为[`StatValue::LOWER`]
+    pub max: T,
+    /// 值的平方和
+    pub sum_of_squares: f64,
 }
 
-impl Stat {
-    /// 创建新的统计结构
-    pub fn new(sum: i64, num: i64) -> Self {
-        Self { sum, num }
+impl<T: StatValue> Default for Stat<T> {
+    fn default() -> Self {
+        Self {
+            sum: T::default(),
+            num: 0,
+            min: T::UPPER,
+            max: T::LOWER,
+            sum_of_squares: 0.0,
+        }
     }
-    
-    /// 获取整数平均值
-    pub fn get_average_int(&self) -> i64 {
+}
+
+impl<T: StatValue> Stat<T> {
+    /// 创建只有总和与数量的统计结构
+    pub fn new(sum: T, num: i64) -> Self {
+        Self {
+            sum,
+            num,
+            ..Self::default()
+        }
+    }
+
+    /// 创建只包含一个样本的统计结构
+    pub fn from_sample(value: T) -> Self {
+        let squared = value.to_f64();
+        Self {
+            sum: value,
+            num: 1,
+            min: value,
+            max: value,
+            sum_of_squares: squared * squared,
+        }
+    }
+
+    /// 获取平均值，整数类型会被截断
+    pub fn get_average(&self) -> T {
         if self.num == 0 {
-            return 0;
+            return T::default();
         }
-        self.sum / self.num
+        self.sum.div_num(self.num)
     }
-    
+
+    /// 获取整数平均值
+    pub fn get_average_int(&self) -> i64 {
+        self.get_average().to_i64()
+    }
+
     /// 获取浮点数平均值
     pub fn get_average_double(&self) -> f64 {
         if self.num == 0 {
             return 0.0;
         }
-        self.sum as f64 / self.num as f64
+        self.sum.to_f64() / self.num as f64
+    }
+
+    /// 获取最小值，没有样本或者最值未知时返回`None`
+    pub fn get_min(&self) -> Option<T> {
+        self.has_extremes().then_some(self.min)
+    }
+
+    /// 获取最大值，没有样本或者最值未知时返回`None`
+    pub fn get_max(&self) -> Option<T> {
+        self.has_extremes().then_some(self.max)
+    }
+
+    /// 最值是否已知，相减得到的结果最值未知，此时`min`大于`max`
+    fn has_extremes(&self) -> bool {
+        self.num > 0 && self.min <= self.max
+    }
+
+    /// 获取总体方差
+    pub fn get_variance(&self) -> f64 {
+        if self.num <= 0 {
+            return 0.0;
+        }
+        let average = self.get_average_double();
+        (self.sum_of_squares / self.num as f64 - average * average).max(0.0)
+    }
+
+    /// 获取总体标准差
+    pub fn get_stddev(&self) -> f64 {
+        self.get_variance().sqrt()
+    }
+
+    /// 把样本数减半，总和与平方和也随之减半，平均值和方差基本不变
+    fn shrink(&mut self) {
+        self.sum = self.sum.halve();
+        self.num /= 2;
+        self.sum_of_squares /= 2.0;
     }
 }
 
-impl std::ops::Sub for Stat {
+impl<T: StatValue> std::ops::Sub for Stat<T> {
     type Output = Self;
-    
+
+    /// 去掉较早的部分，最值无法相减，结果的最值未知
     fn sub(self, rhs: Self) -> Self::Output {
         Self {
-            sum: self.sum - rhs.sum,
+            sum: self.sum.wrapping_sub(rhs.sum),
             num: self.num - rhs.num,
+            min: T::UPPER,
+            max: T::LOWER,
+            sum_of_squares: self.sum_of_squares - rhs.sum_of_squares,
         }
     }
 }
 
-impl std::ops::SubAssign for Stat {
+impl<T: StatValue> std::ops::SubAssign for Stat<T> {
     fn sub_assign(&mut self, rhs: Self) {
-        self.sum -= rhs.sum;
-        self.num -= rhs.num;
+        *self = *self - rhs;
     }
 }
 
-impl std::ops::Add for Stat {
+impl<T: StatValue> std::ops::Add for Stat<T> {
     type Output = Self;
-    
-    fn add(self, rhs: Self) -> Self::Output {
-        Self {
-            sum: self.sum + rhs.sum,
-            num: self.num + rhs.num,
-        }
+
+    fn add(mut self, rhs: Self) -> Self::Output {
+        self += rhs;
+        self
     }
 }
 
-impl std::ops::AddAssign for Stat {
-    fn add_assign(&mut self, rhs: Self) {
-        self.sum += rhs.sum;
+impl<T: StatValue> std::ops::AddAssign for Stat<T> {
+    fn add_assign(&mut self, mut rhs: Self) {
+        loop {
+            if let Some(sum) = self.sum.checked_add(rhs.sum) {
+                self.sum = sum;
+                break;
+            }
+            if self.num <= 1 && rhs.num <= 1 {
+                // 只剩单个样本时无法再缩小
+                self.sum = self.sum.saturating_add(rhs.sum);
+                break;
+            }
+            if self.num > 1 {
+                self.shrink();
+            }
+            if rhs.num > 1 {
+                rhs.shrink();
+            }
+        }
         self.num += rhs.num;
+        self.sum_of_squares += rhs.sum_of_squares;
+        if rhs.min < self.min {
+            self.min = rhs.min;
+        }
+        if rhs.max > self.max {
+            self.max = rhs.max;
+        }
     }
 }
 
-impl fmt::Display for Stat {
+impl<T: StatValue> fmt::Display for Stat<T> {
+    /// 输出浮点数平均值，支持`{:.2}`这样指定精度
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let v = self.get_average_int();
-        if v != 0 {
-            write!(f, "{}", v)
-        } else {
-            write!(f, "{}", self.get_average_double())
+        match f.precision() {
+            Some(precision) => write!(f, "{:.*}", precision, self.get_average_double()),
+            None => write!(f, "{}", self.get_average_double()),
+        }
+    }
+}
+
+/// [`Recorder`]的输出方式，决定`describe`的内容以及导出到监控系统的额外样本
+///
+/// 整数记录器默认输出整数平均值，浮点数记录器默认保留三位小数。
+/// 导出时总是带有`_sum`和`_count`，监控系统可以自行计算平均值。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RecorderDisplay {
+    /// 截断的整数平均值，如`5`
+    #[default]
+    IntAverage,
+    /// 保留指定位数小数的平均值，如`5.25`
+    FloatAverage(usize),
+    /// 总和与样本数，如`21/4`
+    SumNum,
+    /// 平均值、最值和标准差组成的JSON对象，如`{"average":5.250,"min":1,"max":9,"stddev":2.000}`
+    ///
+    /// 没有样本时省略最值，平均值和标准差保留三位小数
+    Stats,
+}
+
+impl RecorderDisplay {
+    /// 按输出方式描述`stat`
+    pub fn describe<T: StatValue>(&self, stat: &Stat<T>, f: &mut String) {
+        let _ = match *self {
+            RecorderDisplay::IntAverage => write!(f, "{}", stat.get_average_int()),
+            RecorderDisplay::FloatAverage(precision) => write!(f, "{:.*}", precision, stat),
+            RecorderDisplay::SumNum => write!(f, "{}/{}", stat.sum, stat.num),
+            RecorderDisplay::Stats => Self::describe_stats(stat, f),
+        };
+    }
+
+    /// 输出JSON对象，没有样本时省略最值
+    fn describe_stats<T: StatValue>(stat: &Stat<T>, f: &mut String) -> fmt::Result {
+        write!(f, "{{\"average\":{}", json_number(format!("{:.3}", stat)))?;
+        if let (Some(min), Some(max)) = (stat.get_min(), stat.get_max()) {
+            write!(
+                f,
+                ",\"min\":{},\"max\":{}",
+                json_number(min.to_string()),
+                json_number(max.to_string())
+            )?;
+        }
+        write!(f, ",\"stddev\":{}}}", json_number(format!("{:.3}", stat.get_stddev())))
+    }
+
+    /// 导出`stat`，所有输出方式都导出`_sum`和`_count`，[`RecorderDisplay::Stats`]还会导出
+    /// `_min`、`_max`和`_stddev`
+    pub fn metric_samples<T: StatValue>(&self, stat: &Stat<T>) -> Vec<MetricSample> {
+        let mut samples = vec![
+            MetricSample::new(stat.sum.to_f64()).with_suffix("_sum"),
+            MetricSample::new(stat.num as f64).with_suffix("_count"),
+        ];
+        if *self == RecorderDisplay::Stats {
+            if let (Some(min), Some(max)) = (stat.get_min(), stat.get_max()) {
+                samples.push(MetricSample::new(min.to_f64()).with_suffix("_min"));
+                samples.push(MetricSample::new(max.to_f64()).with_suffix("_max"));
+            }
+            samples.push(MetricSample::new(stat.get_stddev()).with_suffix("_stddev"));
         }
+        samples
+    }
+}
+
+/// 不是合法JSON数字的值（如NaN）输出为`null`
+fn json_number(value: String) -> String {
+    if is_json_number(&value) {
+        value
+    } else {
+        "null".to_string()
     }
 }
 
-#[derive(Debug)]
-/// 线程本地的Agent
-struct Agent {
-    value: Stat,
+impl<T: StatValue> AgentValue for Stat<T> {
+    type Container = LockedElement<Stat<T>>;
 }
 
-/// 用于计算整数平均值的记录器
-#[derive(Debug)]
-pub struct IntRecorder {
-    /// 线程本地存储
-    tls: ThreadLocal<Mutex<Agent>>,
-    /// 变量名称
-    name: UnsafeCell<String>,
+/// 记录样本的平均值、最值和标准差，类似bvar的`IntRecorder`
+///
+/// 样本类型可以是整数或浮点数：
+///
+/// ```ignore
+/// let latency = IntRecorder::with_name("rpc_latency")?;
+/// latency.add(120);
+/// let ratio = FloatRecorder::with_name("cache_hit_ratio")?;
+/// ratio.add(0.95);
+/// ```
+pub struct Recorder<T: StatValue = i64> {
+    /// 合并各线程的统计
+    combiner: Arc<AgentCombiner<Stat<T>, SumCombiner>>,
+    /// 窗口使用的采样器
+    sampler: SharedSampler,
+    /// 历史数据，默认不开启
+    series: SharedSeries,
+    /// 输出方式，所有克隆共享
+    display: Arc<RwLock<RecorderDisplay>>,
+    /// 暴露信息
+    exposure: Exposure,
     /// 用于调试的名称
     debug_name: String,
 }
 
-// 手动实现线程安全 - 我们确保对UnsafeCell的访问是安全的
-unsafe impl Send for IntRecorder {}
-unsafe impl Sync for IntRecorder {}
+/// 记录整数样本的记录器
+pub type IntRecorder = Recorder<i64>;
 
-impl IntRecorder {
-    /// 创建一个新的整数记录器
+/// 记录浮点数样本的记录器
+pub type FloatRecorder = Recorder<f64>;
+
+impl<T: StatValue> Clone for Recorder<T> {
+    fn clone(&self) -> Self {
+        Self {
+            combiner: self.combiner.clone(),
+            sampler: self.sampler.clone(),
+            series: self.series.clone(),
+            display: self.display.clone(),
+            exposure: self.exposure.clone(),
+            debug_name: self.debug_name.clone(),
+        }
+    }
+}
+
+impl<T: StatValue> Recorder<T> {
+    /// 创建一个新的记录器
     pub fn new() -> Self {
         Self {
-            tls: ThreadLocal::new(),
-            name: UnsafeCell::new(String::new()),
+            combiner: Arc::new(AgentCombiner::new(Stat::default(), SumCombiner, String::new())),
+            sampler: SharedSampler::new(),
+            series: SharedSeries::new(),
+            display: Arc::new(RwLock::new(T::DEFAULT_DISPLAY)),
+            exposure: Exposure::new(),
             debug_name: String::new(),
         }
     }
     
-    /// 用名称创建
-    pub fn with_name(name: &str) -> Self {
+    /// 用名称创建，名称不可用时返回错误
+    pub fn with_name(name: &str) -> Result<Self, ExposeError> {
         let recorder = Self::new();
-        let _ = recorder.expose(name);
-        recorder
+        recorder.expose(name)?;
+        Ok(recorder)
     }
     
-    /// 用前缀和名称创建
-    pub fn with_prefix_name(prefix: &str, name: &str) -> Self {
+    /// 用前缀和名称创建，名称不可用时返回错误
+    pub fn with_prefix_name(prefix: &str, name: &str) -> Result<Self, ExposeError> {
         let recorder = Self::new();
-        let _ = recorder.expose_as(prefix, name);
-        recorder
+        recorder.expose_as(prefix, name)?;
+        Ok(recorder)
     }
     
     /// 添加一个样本
-    pub fn add(&self, sample: i32) -> &Self {
-        // 获取或创建线程本地值
-        let agent = self.tls.get_or(|| {
-            Mutex::new(Agent {
-                value: Stat::default(),
-            })
-        });
-        
-        // 更新值
-        let mut guard = agent.lock();
-        guard.value.sum += sample as i64;
-        guard.value.num += 1;
-        
+    pub fn add(&self, sample: T) -> &Self {
+        self.combiner.add(Stat::from_sample(sample));
         self
     }
     
@@ -172,71 +490,137 @@ impl IntRecorder {
     }
     
     /// 获取当前统计值
-    pub fn get_value(&self) -> Stat {
-        let mut result = Stat::default();
-        
-        for agent in self.tls.iter() {
-            let agent_value = agent.lock().value.clone();
-            result += agent_value;
-        }
-        
-        result
+    pub fn get_value(&self) -> Stat<T> {
+        self.combiner.combine_agents()
     }
     
-    /// 重置所有值
-    pub fn reset(&self) -> Stat {
-        let result = self.get_value();
-        
-        for agent in self.tls.iter() {
-            let mut guard = agent.lock();
-            guard.value = Stat::default();
-        }
-        
-        result
+    /// 重置所有值，并返回重置前的统计值
+    pub fn reset(&self) -> Stat<T> {
+        self.combiner.reset_all_agents()
     }
     
+    /// 设置输出方式
+    pub fn with_display(self, display: RecorderDisplay) -> Self {
+        self.set_display(display);
+        self
+    }
+
+    /// 修改输出方式，对所有克隆以及已经暴露的变量生效
+    pub fn set_display(&self, display: RecorderDisplay) {
+        *self.display.write() = display;
+    }
+
+    /// 获取输出方式
+    pub fn display(&self) -> RecorderDisplay {
+        *self.display.read()
+    }
+
+    /// 开启历史数据
+    pub fn with_series(self) -> Self {
+        self.enable_series();
+        self
+    }
+
+    /// 开启历史数据，每秒记录一次平均值，合并为更粗的粒度时取平均值
+    ///
+    /// 对所有克隆以及已经暴露的变量生效
+    pub fn enable_series(&self) {
+        let combiner = self.combiner.clone();
+        self.series.enable(|| {
+            SeriesSampler::new(Series::averaged(AddTo::<f64>::default()), move || {
+                combiner.combine_agents().get_average_double()
+            })
+        });
+    }
+
     /// 设置用于调试的名称
     pub fn set_debug_name(&mut self, name: &str) {
         self.debug_name = name.to_string();
     }
 }
 
-impl Variable for IntRecorder {
+impl<T: StatValue> Variable for Recorder<T> {
+    /// 按[`RecorderDisplay`]输出
     fn describe(&self, f: &mut String, _quote_string: bool) -> bool {
-        let _ = write!(f, "{}", self.get_value());
+        self.display().describe(&self.get_value(), f);
         true
     }
     
-    fn expose_impl(&self, prefix: &str, name: &str) -> i32 {
-        // 更新内部名称
-        let mut full_name = String::new();
-        if !prefix.is_empty() {
-            full_name.push_str(prefix);
-            full_name.push('_');
-        }
-        println!("expose_impl: {}", name);
-        full_name.push_str(name);
-        
-        // 将自己暴露出去
-        // let result = <dyn Variable>::default_expose_impl(self, prefix, name);
-        // let result = Variable::default_expose_impl(self, prefix, name);
-        let result = <IntRecorder as Variable>::default_expose_impl(&self, prefix, name);
-        if result == 0 {
-            // 仅在成功时更新名称
-            // 使用UnsafeCell安全地更新内部状态
-            unsafe {
-                *self.name.get() = full_name;
-            }
-        }
-        result
+    fn expose_impl(&self, prefix: &str, name: &str) -> Result<(), ExposeError> {
+        self.default_expose_impl(prefix, name)
     }
-    
-    fn name(&self) -> String {
-        unsafe { (*self.name.get()).clone() }
+
+    fn exposure(&self) -> Option<&Exposure> {
+        Some(&self.exposure)
+    }
+
+    /// 导出为带有`_sum`和`_count`的summary
+    fn metric_kind(&self) -> MetricKind {
+        MetricKind::Summary
+    }
+
+    fn metric_samples(&self) -> Vec<MetricSample> {
+        self.display().metric_samples(&self.get_value())
+    }
+
+    fn describe_series(&self, f: &mut dyn fmt::Write, options: &SeriesOptions) -> bool {
+        self.series.describe(self, f, options)
     }
 }
 
-impl Default for IntRecorder {
+impl<T: StatValue> ReducerTrait<Stat<T>, SumCombiner> for Recorder<T> {
+    fn get_value(&self) -> Stat<T> {
+        self.get_value()
+    }
+
+    fn reset(&self) -> Stat<T> {
+        self.reset()
+    }
+
+    fn op(&self) -> SumCombiner {
+        SumCombiner
+    }
+}
+
+impl<T: StatValue> WindowSource for Recorder<T> {
+    type Value = Stat<T>;
+    type Op = SumCombiner;
+    type InvOp = MinusFrom<Stat<T>>;
+
+    fn inv_op(&self) -> MinusFrom<Stat<T>> {
+        MinusFrom::default()
+    }
+
+    fn shared_sampler(&self) -> &SharedSampler {
+        &self.sampler
+    }
+
+    /// 窗口内的值也按[`RecorderDisplay`]输出
+    fn describe_value(&self, value: &Stat<T>, f: &mut String) {
+        self.display().describe(value, f);
+    }
+
+    fn value_metric_kind(&self) -> MetricKind {
+        MetricKind::Summary
+    }
+
+    fn value_metric_samples(&self, value: &Stat<T>) -> Vec<MetricSample> {
+        self.display().metric_samples(value)
+    }
+}
+
+impl<T: StatValue> fmt::Debug for Recorder<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Recorder")
+            .field("value", &self.get_value())
+            .field("display", &self.display())
+            .field("exposure", &self.exposure)
+            .field("debug_name", &self.debug_name)
+            .finish()
+    }
+}
+
+impl<T: StatValue> Default for Recorder<T> {
     fn default() -> Self {
         Self::new()
     }
@@ -245,11 +629,14 @@ impl Default for IntRecorder {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::detail::clock::MockClock;
+    use crate::window::Window;
+    use std::time::Duration;
     
     #[test]
     fn test_int_recorder() {
         let recorder = IntRecorder::new();
-        let _ = recorder.expose("test");
+        let _ = recorder.expose("recorder_test");
         let value = recorder.get_value();
         assert_eq!(value.sum , 0);
         assert_eq!(value.num , 0);
@@ -270,4 +657,131 @@ mod tests {
         assert_eq!(value.num , 0);
         
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_int_recorder_thread_exit() {
+        let recorder = IntRecorder::new();
+        for round in 0..4 {
+            let threads: Vec<_> = (0..16)
+                .map(|_| {
+                    let recorder = recorder.clone();
+                    std::thread::spawn(move || {
+                        for i in 0..100 {
+                            recorder.add(i);
+                        }
+                    })
+                })
+                .collect();
+            for thread in threads {
+                thread.join().unwrap();
+            }
+
+            // 退出线程的统计被合并到全局结果中，Agent也被释放
+            let value = recorder.get_value();
+            assert_eq!(value.num, 16 * 100 * (round + 1));
+            assert_eq!(value.sum, 16 * 4950 * (round + 1));
+            assert_eq!(recorder.combiner.agent_count(), 0);
+        }
+    }
+
+    #[test]
+    fn test_stat() {
+        let mut stat = Stat::default();
+        assert_eq!(stat.get_min(), None);
+        for value in [2, 4, 4, 4, 5, 5, 7, 9] {
+            stat += Stat::from_sample(value);
+        }
+        assert_eq!(stat.get_average_int(), 5);
+        assert_eq!((stat.get_min(), stat.get_max()), (Some(2), Some(9)));
+        assert_eq!(stat.get_variance(), 4.0);
+        assert_eq!(stat.get_stddev(), 2.0);
+
+        // 总和溢出时把sum和num同时减半，平均值不变
+        let mut large = Stat::new(i64::MAX - 10, 4);
+        large += Stat::new(i64::MAX / 2, 2);
+        assert_eq!(large.num, 3);
+        assert!((large.get_average_int() - i64::MAX / 4).abs() < 10);
+        let mut single = Stat::from_sample(i64::MAX);
+        single += Stat::from_sample(1);
+        assert_eq!((single.sum, single.num), (i64::MAX, 2));
+    }
+
+    #[test]
+    fn test_windowed_stat() {
+        let clock = Arc::new(MockClock::new());
+        let recorder = IntRecorder::new().with_display(RecorderDisplay::Stats);
+        let window = Window::with_clock(&recorder, 2, clock.clone());
+        window.take_sample();
+        recorder.add(1000);
+        for value in [10, 20, 30] {
+            clock.advance(Duration::from_secs(1));
+            window.take_sample();
+            recorder.add(value);
+        }
+        clock.advance(Duration::from_secs(1));
+        window.take_sample();
+
+        // 窗口已经不包含1000，相减得到的最值未知，不会输出整个生命周期的最大值
+        let stat = window.get_value().unwrap();
+        assert_eq!((stat.num, stat.get_average_int()), (2, 25));
+        assert_eq!((stat.get_min(), stat.get_max()), (None, None));
+        assert_eq!(window.get_description(), r#"{"average":25.000,"stddev":5.000}"#);
+        assert_eq!(recorder.get_value().get_max(), Some(1000));
+    }
+
+    #[test]
+    fn test_float_recorder() {
+        let recorder = FloatRecorder::new();
+        recorder.add(0.5).add(1.5);
+        let value = recorder.get_value();
+        assert_eq!(value.get_average(), 1.0);
+        assert_eq!(value.get_stddev(), 0.5);
+        assert_eq!(recorder.get_description(), "1.000");
+        assert_eq!(recorder.metric_kind(), MetricKind::Summary);
+        assert_eq!(IntRecorder::new().get_description(), "0");
+    }
+
+    #[test]
+    fn test_recorder_display() {
+        let recorder = IntRecorder::new().with_display(RecorderDisplay::IntAverage);
+        recorder.add(1).add(2);
+        assert_eq!(recorder.get_description(), "1");
+        assert_eq!(recorder.metric_kind(), MetricKind::Summary);
+        assert_eq!(
+            recorder.metric_samples(),
+            vec![
+                MetricSample::new(3.0).with_suffix("_sum"),
+                MetricSample::new(2.0).with_suffix("_count"),
+            ]
+        );
+
+        // 克隆共享输出方式
+        recorder.clone().set_display(RecorderDisplay::FloatAverage(2));
+        assert_eq!(recorder.get_description(), "1.50");
+        recorder.add(2);
+        assert_eq!(recorder.get_description(), "1.67");
+
+        recorder.set_display(RecorderDisplay::SumNum);
+        assert_eq!(recorder.get_description(), "5/3");
+        assert_eq!(recorder.metric_samples()[0], MetricSample::new(5.0).with_suffix("_sum"));
+
+        recorder.set_display(RecorderDisplay::Stats);
+        assert_eq!(
+            recorder.get_description(),
+            r#"{"average":1.667,"min":1,"max":2,"stddev":0.471}"#
+        );
+        let samples = recorder.metric_samples();
+        assert_eq!(samples[2], MetricSample::new(1.0).with_suffix("_min"));
+        assert_eq!(samples[3], MetricSample::new(2.0).with_suffix("_max"));
+        assert_eq!(samples[4].suffix, "_stddev");
+        assert_eq!(
+            IntRecorder::new().with_display(RecorderDisplay::Stats).get_description(),
+            r#"{"average":0.000,"stddev":0.000}"#
+        );
+
+        // Stat总是输出浮点数平均值
+        assert_eq!(Stat::new(1, 2).to_string(), "0.5");
+        assert_eq!(Stat::new(4, 2).to_string(), "2");
+        assert_eq!(format!("{:.1}", Stat::new(1, 3)), "0.3");
+    }
+}
@@ -15,17 +15,42 @@
 //! 实现用于将多个值规约为一个值的操作，如求和、求最大值等
 
 use std::fmt;
-use crate::variable::Variable;
-use crate::detail::combiner::AgentCombiner;
-use crate::detail::combiner::Combiner;
+use crate::export::MetricKind;
+use crate::variable::{ExposeError, Exposure, Variable};
+use crate::detail::combiner::{AgentCombiner, AgentValue, Combiner, InverseOp};
+use crate::detail::sampler::{SeriesSampler, SharedSampler, SharedSeries};
+use crate::detail::series::Series;
+use crate::variable::SeriesOptions;
+use crate::window::WindowSource;
 use std::fmt::Write;
 
 /// 表示一个无效的反向操作
 #[derive(Clone)]
 pub struct VoidOp;
 
+impl<T> InverseOp<T> for VoidOp {
+    fn invertible(&self) -> bool {
+        false
+    }
+
+    fn inverse(&self, v1: T, _v2: T) -> T {
+        v1
+    }
+}
+
+/// 组合操作对应的逆操作，决定[`Reducer`]作为窗口数据源时如何计算窗口内的值
+///
+/// 求和这类可逆的操作用最新值减去窗口起点的值；最大值这类不可逆的操作为[`VoidOp`]，
+/// 每次采样都会重置Reducer
+pub trait WithInverse<T>: Combiner<T> {
+    /// 逆操作的类型
+    type Inverse: InverseOp<T>;
+
+    /// 获取逆操作
+    fn inverse_op(&self) -> Self::Inverse;
+}
+
 use std::sync::Arc;
-use parking_lot::Mutex;
 
 
 pub trait ReducerTrait<T, Op> {
@@ -43,60 +68,78 @@ pub trait ReducerTrait<T, Op> {
 ///   - 结合性:     a Op (b Op c) == (a Op b) Op c
 ///   - 交换性:     a Op b == b Op a;
 ///   - 无副作用:   a Op b在a和b固定时永远产生相同结果
+///
+/// `add`只修改当前线程的Agent，不需要任何全局锁，可以放在静态变量中被多个线程共享：
+///
+/// ```ignore
+/// static REQUESTS: Lazy<Adder<i64>> = Lazy::new(Adder::new);
+/// REQUESTS.add(1);
+/// ```
 #[derive(Clone)]
 pub struct Reducer<T, Op> where
-T: Clone + Send + Sync,
+T: AgentValue,
 Op: Combiner<T> + Send + Sync + 'static + Clone,
 {
     /// 内部组合器
-    combiner: Arc<Mutex<AgentCombiner<T, Op>>>,
-    /// 最后一次暴露的名称
-    _name: String,
+    combiner: Arc<AgentCombiner<T, Op>>,
+    /// 窗口使用的采样器
+    sampler: SharedSampler,
+    /// 历史数据，默认不开启
+    series: SharedSeries,
+    /// 暴露信息
+    exposure: Exposure,
 }
 
 impl<T, Op> Reducer<T, Op>
 where
-    T: Clone + Send + Sync + fmt::Display + 'static,
+    T: AgentValue + fmt::Display,
     Op: Combiner<T> + Send + Sync + 'static + Clone,
 {
     /// 创建新的Reducer
     pub fn new(identity: T, op: Op, name: String) -> Self {
         Self {
-            combiner: Arc::new(Mutex::new(AgentCombiner::new(identity, op, name))),
-            _name: String::new(),
+            combiner: Arc::new(AgentCombiner::new(identity, op, name)),
+            sampler: SharedSampler::new(),
+            series: SharedSeries::new(),
+            exposure: Exposure::new(),
         }
     }
     
-    /// 添加一个值
-    pub fn add(&mut self, value: T) -> &Self {
-        let op = self.combiner.lock().op().clone();
-        if let Some(agent) = self.combiner.lock().get_or_create_tls_agent() {
-            let guard = agent.lock();
-            op.combine(guard.value.clone(), value);
-        }
+    /// 添加一个值，只修改当前线程的Agent
+    pub fn add(&self, value: T) -> &Self {
+        self.combiner.add(value);
         self
     }
     
     /// 获取规约后的值
     pub fn get_value(&self) -> T {
-        self.combiner.lock().combine_agents()
+        self.combiner.combine_agents()
     }
     
     /// 重置规约的值为identity
     pub fn reset(&self) -> T {
-        self.combiner.lock().reset_all_agents()
+        self.combiner.reset_all_agents()
     }
     
     /// 获取操作符实例
     pub fn op(&self) -> Op {
-        self.combiner.lock().op().clone()
+        self.combiner.op().clone()
+    }
+
+    /// 开启历史数据，`series`决定如何把秒级数据合并为更粗的粒度
+    pub fn enable_series(&self, series: Series<T, Op>)
+    where
+        T: Default + fmt::Debug,
+    {
+        let combiner = self.combiner.clone();
+        self.series.enable(|| SeriesSampler::new(series, move || combiner.combine_agents()));
     }
 
 }
 
 impl<T, Op> ReducerTrait<T, Op> for Reducer<T, Op>
 where
-    T: Clone + Send + Sync + fmt::Display + 'static,
+    T: AgentValue + fmt::Display,
     Op: Combiner<T> + Send + Sync + 'static + Clone,
 {
     fn get_value(&self) -> T {
@@ -114,7 +157,7 @@ where
 
 impl<T, Op> Variable for Reducer<T, Op>
 where
-    T: Clone + Send + Sync + fmt::Display + 'static,
+    T: AgentValue + fmt::Display,
     Op: Combiner<T> + Send + Sync + 'static + Clone,
 {
     fn describe(&self, f: &mut String, _quote_string: bool) -> bool {
@@ -122,31 +165,21 @@ where
         true
     }
     
-    fn expose_impl(&self, prefix: &str, name: &str) -> i32 {
-        // 更新内部名称
-        let mut full_name = String::new();
-        if !prefix.is_empty() {
-            full_name.push_str(prefix);
-            full_name.push('_');
-        }
-        full_name.push_str(name);
-        
-        // 将自己暴露出去
-        let result = <dyn Variable>::default_expose_impl(self, prefix, name);
-        if result == 0 {
-            // 仅在成功时更新名称
-            self.combiner.lock().set_name(full_name);
-        }
-        result
+    fn expose_impl(&self, prefix: &str, name: &str) -> Result<(), ExposeError> {
+        self.default_expose_impl(prefix, name)
     }
-    
-    fn name(&self) -> String {
-        self.combiner.lock().name().to_string()
+
+    fn exposure(&self) -> Option<&Exposure> {
+        Some(&self.exposure)
+    }
+
+    fn describe_series(&self, f: &mut dyn fmt::Write, options: &SeriesOptions) -> bool {
+        self.series.describe(self, f, options)
     }
 }   
 
 // 常用组合器的实现
-use num_traits::NumOps;
+use num_traits::{FromPrimitive, NumOps};
 
 use std::marker::PhantomData;
 /// 加法操作
@@ -193,15 +226,33 @@ impl<T: NumOps + Clone + Send + Sync + 'static> Combiner<T> for MinusFrom<T> {
     }
 }
 
+impl<T: NumOps + Clone + Send + Sync + 'static> WithInverse<T> for AddTo<T> {
+    type Inverse = MinusFrom<T>;
+
+    fn inverse_op(&self) -> MinusFrom<T> {
+        MinusFrom::default()
+    }
+}
+
+impl<T> InverseOp<T> for MinusFrom<T>
+where
+    T: std::ops::Sub<Output = T> + Clone + Send + Sync + 'static,
+{
+    fn inverse(&self, v1: T, v2: T) -> T {
+        v1 - v2
+    }
+}
+
 /// 求和器
-pub struct Adder<T> where T: std::ops::Mul<Output = T> + std::ops::Sub<Output = T> + std::ops::Add<Output = T> + std::ops::Rem<Output = T> + std::ops::Div<Output = T> + Clone + Send + Sync + 'static {
+#[derive(Clone)]
+pub struct Adder<T> where T: std::ops::Mul<Output = T> + std::ops::Sub<Output = T> + std::ops::Add<Output = T> + std::ops::Rem<Output = T> + std::ops::Div<Output = T> + AgentValue {
     inner: Reducer<T, AddTo<T>>,
 
 }
 
 impl<T> Adder<T>
 where
-    T: Clone + Send + Sync + fmt::Display + NumOps + Default + 'static,
+    T: AgentValue + fmt::Display + NumOps + Default,
 {
     /// 创建新的加法器
     pub fn new() -> Self {
@@ -210,22 +261,22 @@ where
         }
     }
     
-    /// 使用名称创建
-    pub fn with_name(name: &str) -> Self {
+    /// 使用名称创建，名称不可用时返回错误
+    pub fn with_name(name: &str) -> Result<Self, ExposeError> {
         let adder = Self::new();
-        let _ = adder.expose(name);
-        adder
+        adder.expose(name)?;
+        Ok(adder)
     }
     
-    /// 使用前缀和名称创建
-    pub fn with_prefix_name(prefix: &str, name: &str) -> Self {
+    /// 使用前缀和名称创建，名称不可用时返回错误
+    pub fn with_prefix_name(prefix: &str, name: &str) -> Result<Self, ExposeError> {
         let adder = Self::new();
-        let _ = adder.expose_as(prefix, name);
-        adder
+        adder.expose_as(prefix, name)?;
+        Ok(adder)
     }
     
     /// 添加一个值
-    pub fn add(&mut self, value: T) -> &Self {
+    pub fn add(&self, value: T) -> &Self {
         self.inner.add(value);
         self
     }
@@ -239,29 +290,56 @@ where
     pub fn reset(&self) -> T {
         self.inner.reset()
     }
+
+    /// 开启历史数据
+    pub fn with_series(self) -> Self
+    where
+        T: fmt::Debug + FromPrimitive,
+    {
+        self.enable_series();
+        self
+    }
+
+    /// 开启历史数据，每秒记录一次当前值，合并为更粗的粒度时取平均值
+    ///
+    /// 对所有克隆以及已经暴露的变量生效
+    pub fn enable_series(&self)
+    where
+        T: fmt::Debug + FromPrimitive,
+    {
+        self.inner.enable_series(Series::averaged(AddTo::default()));
+    }
 }
 
 impl<T> Variable for Adder<T>
 where
-    T: Clone + Send + Sync + fmt::Display + NumOps + Default + 'static,
+    T: AgentValue + fmt::Display + NumOps + Default,
 {
     fn describe(&self, f: &mut String, quote_string: bool) -> bool {
         self.inner.describe(f, quote_string);
         true
     }
     
-    fn expose_impl(&self, prefix: &str, name: &str) -> i32 {
-        self.inner.expose_impl(prefix, name)
+    fn expose_impl(&self, prefix: &str, name: &str) -> Result<(), ExposeError> {
+        self.default_expose_impl(prefix, name)
     }
-    
-    fn name(&self) -> String {
-        self.inner.name()
+
+    fn exposure(&self) -> Option<&Exposure> {
+        self.inner.exposure()
+    }
+
+    fn describe_series(&self, f: &mut dyn fmt::Write, options: &SeriesOptions) -> bool {
+        self.inner.describe_series(f, options)
+    }
+
+    fn metric_kind(&self) -> MetricKind {
+        MetricKind::Counter
     }
 }
 
 impl<T> Default for Adder<T>
 where
-    T: Clone + Send + Sync + fmt::Display + NumOps + Default + 'static,
+    T: AgentValue + fmt::Display + NumOps + Default,
 {
     fn default() -> Self {
         Self::new()
@@ -278,6 +356,14 @@ impl<T> Default for MaxTo<T> {
     }
 }
 
+impl<T: PartialOrd + Clone + Send + Sync + 'static> WithInverse<T> for MaxTo<T> {
+    type Inverse = VoidOp;
+
+    fn inverse_op(&self) -> VoidOp {
+        VoidOp
+    }
+}
+
 impl<T: PartialOrd + Clone + Send + Sync + 'static> Combiner<T> for MaxTo<T> {
     fn combine(&self, lhs: T, rhs: T) -> T {
         if rhs > lhs {
@@ -295,13 +381,14 @@ impl<T: PartialOrd + Clone + Send + Sync + 'static> Combiner<T> for MaxTo<T> {
 }
 
 /// 求最大值器
-pub struct Maxer<T> where T: PartialOrd + Send + Clone + Sync + 'static {
+#[derive(Clone)]
+pub struct Maxer<T> where T: PartialOrd + AgentValue {
     inner: Reducer<T, MaxTo<T>>,
 }
 
 impl<T> Maxer<T>
 where
-    T: Clone + Send + Sync + fmt::Display + PartialOrd + 'static,
+    T: AgentValue + fmt::Display + PartialOrd,
 {
     /// 创建新的最大值器
     pub fn new(default_value: T) -> Self {
@@ -310,22 +397,22 @@ where
         }
     }
     
-    /// 使用名称创建
-    pub fn with_name(default_value: T, name: &str) -> Self {
+    /// 使用名称创建，名称不可用时返回错误
+    pub fn with_name(default_value: T, name: &str) -> Result<Self, ExposeError> {
         let maxer = Self::new(default_value);
-        let _ = maxer.expose(name);
-        maxer
+        maxer.expose(name)?;
+        Ok(maxer)
     }
     
-    /// 使用前缀和名称创建
-    pub fn with_prefix_name(default_value: T, prefix: &str, name: &str) -> Self {
+    /// 使用前缀和名称创建，名称不可用时返回错误
+    pub fn with_prefix_name(default_value: T, prefix: &str, name: &str) -> Result<Self, ExposeError> {
         let maxer = Self::new(default_value);
-        let _ = maxer.expose_as(prefix, name);
-        maxer
+        maxer.expose_as(prefix, name)?;
+        Ok(maxer)
     }
     
     /// 添加一个值
-    pub fn add(&mut self, value: T) -> &Self {
+    pub fn add(&self, value: T) -> &Self {
         self.inner.add(value);
         self
     }
@@ -339,23 +426,46 @@ where
     pub fn reset(&self) -> T {
         self.inner.reset()
     }
+
+    /// 开启历史数据
+    pub fn with_series(self) -> Self
+    where
+        T: Default + fmt::Debug,
+    {
+        self.enable_series();
+        self
+    }
+
+    /// 开启历史数据，每秒记录一次当前值，合并为更粗的粒度时取最大值
+    ///
+    /// 对所有克隆以及已经暴露的变量生效
+    pub fn enable_series(&self)
+    where
+        T: Default + fmt::Debug,
+    {
+        self.inner.enable_series(Series::new(MaxTo::default()));
+    }
 }
 
 impl<T> Variable for Maxer<T>
 where
-    T: Clone + Send + Sync + fmt::Display + PartialOrd + 'static,
+    T: AgentValue + fmt::Display + PartialOrd,
 {
     fn describe(&self, f: &mut String, quote_string: bool) -> bool {
         self.inner.describe(f, quote_string);
         true
     }
     
-    fn expose_impl(&self, prefix: &str, name: &str) -> i32 {
-        self.inner.expose_impl(prefix, name)
+    fn expose_impl(&self, prefix: &str, name: &str) -> Result<(), ExposeError> {
+        self.default_expose_impl(prefix, name)
     }
-    
-    fn name(&self) -> String {
-        self.inner.name()
+
+    fn exposure(&self) -> Option<&Exposure> {
+        self.inner.exposure()
+    }
+
+    fn describe_series(&self, f: &mut dyn fmt::Write, options: &SeriesOptions) -> bool {
+        self.inner.describe_series(f, options)
     }
 }
 
@@ -369,6 +479,14 @@ impl<T> Default for MinTo<T> {
     }
 }
 
+impl<T: PartialOrd + Clone + Send + Sync + 'static> WithInverse<T> for MinTo<T> {
+    type Inverse = VoidOp;
+
+    fn inverse_op(&self) -> VoidOp {
+        VoidOp
+    }
+}
+
 impl<T: PartialOrd + Clone + Send + Sync + 'static> Combiner<T> for MinTo<T> {
     fn combine(&self, lhs: T, rhs: T) -> T {
         if rhs < lhs {
@@ -386,13 +504,14 @@ impl<T: PartialOrd + Clone + Send + Sync + 'static> Combiner<T> for MinTo<T> {
 }
 
 /// 求最小值器
-pub struct Miner<T> where T: PartialOrd + Clone + Send + Sync + 'static {
+#[derive(Clone)]
+pub struct Miner<T> where T: PartialOrd + AgentValue {
     inner: Reducer<T, MinTo<T>>,
 }
 
 impl<T> Miner<T>
 where
-    T: Clone + Send + Sync + fmt::Display + PartialOrd + 'static,
+    T: AgentValue + fmt::Display + PartialOrd,
 {
     /// 创建新的最小值器
     pub fn new(default_value: T) -> Self {
@@ -401,22 +520,22 @@ where
         }
     }
     
-    /// 使用名称创建
-    pub fn with_name(default_value: T, name: &str) -> Self {
+    /// 使用名称创建，名称不可用时返回错误
+    pub fn with_name(default_value: T, name: &str) -> Result<Self, ExposeError> {
         let miner = Self::new(default_value);
-        let _ = miner.expose(name);
-        miner
+        miner.expose(name)?;
+        Ok(miner)
     }
     
-    /// 使用前缀和名称创建
-    pub fn with_prefix_name(default_value: T, prefix: &str, name: &str) -> Self {
+    /// 使用前缀和名称创建，名称不可用时返回错误
+    pub fn with_prefix_name(default_value: T, prefix: &str, name: &str) -> Result<Self, ExposeError> {
         let miner = Self::new(default_value);
-        let _ = miner.expose_as(prefix, name);
-        miner
+        miner.expose_as(prefix, name)?;
+        Ok(miner)
     }
     
     /// 添加一个值
-    pub fn add(&mut self, value: T) -> &Self {
+    pub fn add(&self, value: T) -> &Self {
         self.inner.add(value);
         self
     }
@@ -434,22 +553,83 @@ where
 
 impl<T> Variable for Miner<T>
 where
-    T: Clone + Send + Sync + fmt::Display + PartialOrd + 'static,
+    T: AgentValue + fmt::Display + PartialOrd,
 {
     fn describe(&self, f: &mut String, quote_string: bool) ->  bool {
         self.inner.describe(f, quote_string);
         true
     }
     
-    fn expose_impl(&self, prefix: &str, name: &str) -> i32 {
-        self.inner.expose_impl(prefix, name)
+    fn expose_impl(&self, prefix: &str, name: &str) -> Result<(), ExposeError> {
+        self.default_expose_impl(prefix, name)
     }
-    
-    fn name(&self) -> String {
-        self.inner.name()
+
+    fn exposure(&self) -> Option<&Exposure> {
+        self.inner.exposure()
     }
 }
 
+impl<T, Op> WindowSource for Reducer<T, Op>
+where
+    T: AgentValue + fmt::Display,
+    Op: WithInverse<T> + Send + Sync + 'static + Clone,
+{
+    type Value = T;
+    type Op = Op;
+    type InvOp = Op::Inverse;
+
+    fn inv_op(&self) -> Op::Inverse {
+        self.combiner.op().inverse_op()
+    }
+
+    fn shared_sampler(&self) -> &SharedSampler {
+        &self.sampler
+    }
+}
+
+/// 为包装了Reducer的变量实现`ReducerTrait`和`WindowSource`
+macro_rules! impl_reducer_source {
+    ($name:ident<$t:ident>, $op:ident, $inv_op:ty, $inv:expr, $($bound:tt)+) => {
+        impl<$t> ReducerTrait<$t, $op<$t>> for $name<$t>
+        where
+            $t: $($bound)+,
+        {
+            fn get_value(&self) -> $t {
+                self.inner.get_value()
+            }
+
+            fn reset(&self) -> $t {
+                self.inner.reset()
+            }
+
+            fn op(&self) -> $op<$t> {
+                self.inner.op()
+            }
+        }
+
+        impl<$t> WindowSource for $name<$t>
+        where
+            $t: $($bound)+,
+        {
+            type Value = $t;
+            type Op = $op<$t>;
+            type InvOp = $inv_op;
+
+            fn inv_op(&self) -> $inv_op {
+                $inv
+            }
+
+            fn shared_sampler(&self) -> &SharedSampler {
+                &self.inner.sampler
+            }
+        }
+    };
+}
+
+impl_reducer_source!(Adder<T>, AddTo, MinusFrom<T>, MinusFrom::default(), AgentValue + fmt::Display + NumOps + Default);
+impl_reducer_source!(Maxer<T>, MaxTo, VoidOp, VoidOp, AgentValue + fmt::Display + PartialOrd);
+impl_reducer_source!(Miner<T>, MinTo, VoidOp, VoidOp, AgentValue + fmt::Display + PartialOrd);
+
 /// 提供求和操作
 #[derive(Clone)]
 pub struct SumCombiner;
@@ -471,10 +651,32 @@ where
     }
 }
 
+impl<T> WithInverse<T> for SumCombiner
+where
+    T: std::ops::Add<Output = T> + std::ops::Sub<Output = T> + Clone + Send + Sync + 'static,
+{
+    type Inverse = MinusFrom<T>;
+
+    fn inverse_op(&self) -> MinusFrom<T> {
+        MinusFrom::default()
+    }
+}
+
 /// 提供求最大值操作
 #[derive(Clone)]
 pub struct MaxCombiner;
 
+impl<T> WithInverse<T> for MaxCombiner
+where
+    T: std::cmp::PartialOrd + Clone,
+{
+    type Inverse = VoidOp;
+
+    fn inverse_op(&self) -> VoidOp {
+        VoidOp
+    }
+}
+
 impl<T> Combiner<T> for MaxCombiner
 where
     T: std::cmp::PartialOrd + Clone,
@@ -500,6 +702,17 @@ where
 #[derive(Clone)]
 pub struct MinCombiner;
 
+impl<T> WithInverse<T> for MinCombiner
+where
+    T: std::cmp::PartialOrd + Clone,
+{
+    type Inverse = VoidOp;
+
+    fn inverse_op(&self) -> VoidOp {
+        VoidOp
+    }
+}
+
 impl<T> Combiner<T> for MinCombiner
 where
     T: std::cmp::PartialOrd + Clone,
@@ -569,14 +782,19 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::detail::clock::MockClock;
+    use crate::detail::combiner::ElementContainer;
     use crate::variable::Variable;
+    use crate::window::Window;
+    use once_cell::sync::Lazy;
+    use std::time::Duration;
 
 
     #[test]
     fn test_reducer() {
-        let mut reducer = Reducer::new(0, AddTo::default(), "test".to_string());
-        let _ = reducer.expose("test");
-        let _ = reducer.expose_as("prefix", "test");
+        let reducer = Reducer::new(0, AddTo::default(), "test".to_string());
+        let _ = reducer.expose("reducer_test");
+        let _ = reducer.expose_as("prefix", "reducer_test");
         let _ = reducer.add(1);
         let _ = reducer.add(2);
         let _ = reducer.add(3);
@@ -584,6 +802,186 @@ mod tests {
         let _ = reducer.add(5);
         let _ = reducer.add(6);
         let _ = reducer.add(7);
-        let _ = reducer.reset();
-    }   
+        assert_eq!(reducer.get_value(), 28);
+        assert_eq!(reducer.reset(), 28);
+        assert_eq!(reducer.get_value(), 0);
+    }
+
+    #[test]
+    fn test_reducer_window() {
+        let clock = Arc::new(MockClock::new());
+        let reducer = Reducer::new(0i64, AddTo::default(), "test".to_string());
+        let sum_in_2s = Window::with_clock(&reducer, 2, clock.clone());
+        for value in [1, 2, 3, 4] {
+            reducer.add(value);
+            clock.advance(Duration::from_secs(1));
+            sum_in_2s.take_sample();
+        }
+        // 求和可逆，窗口内的值为最新值减去窗口起点的值，采样不会重置Reducer
+        assert_eq!(sum_in_2s.get_value(), Some(7));
+        assert_eq!(reducer.get_value(), 10);
+
+        // 求最大值不可逆，每次采样都会重置Reducer
+        let maxer = Reducer::new(0i64, MaxTo::default(), "test".to_string());
+        let max_in_2s = Window::with_clock(&maxer, 2, clock.clone());
+        for value in [9, 5, 7] {
+            maxer.add(value);
+            clock.advance(Duration::from_secs(1));
+            max_in_2s.take_sample();
+        }
+        assert_eq!(max_in_2s.get_value(), Some(7));
+        assert_eq!(maxer.get_value(), 0);
+    }
+
+    static REQUESTS: Lazy<Adder<i64>> = Lazy::new(Adder::new);
+
+    #[test]
+    fn test_static_adder_from_many_threads() {
+        // 主线程在所有线程存活时检查Agent，然后再让它们退出
+        let alive = Arc::new(std::sync::Barrier::new(65));
+        let checked = Arc::new(std::sync::Barrier::new(65));
+        let threads: Vec<_> = (0..64)
+            .map(|_| {
+                let (alive, checked) = (alive.clone(), checked.clone());
+                std::thread::spawn(move || {
+                    for _ in 0..1000 {
+                        REQUESTS.add(1);
+                    }
+                    // 每个线程只修改自己的Agent
+                    let value = REQUESTS.inner.combiner.get_or_create_tls_agent().unwrap().element.get();
+                    alive.wait();
+                    checked.wait();
+                    value
+                })
+            })
+            .collect();
+
+        alive.wait();
+        assert_eq!(REQUESTS.inner.combiner.agent_count(), 64);
+        let total: i64 = REQUESTS.inner.combiner.agents().iter().map(|agent| agent.element.get()).sum();
+        assert_eq!(total, 64 * 1000);
+        checked.wait();
+
+        for thread in threads {
+            assert_eq!(thread.join().unwrap(), 1000);
+        }
+        // 退出的线程把值提交到全局结果中
+        assert_eq!(REQUESTS.inner.combiner.agent_count(), 0);
+        assert_eq!(REQUESTS.get_value(), 64 * 1000);
+    }
+
+    /// 在`threads`个线程中同时执行`f(线程序号)`
+    fn run_threads<F>(threads: usize, f: F)
+    where
+        F: Fn(usize) + Send + Sync + 'static,
+    {
+        let f = Arc::new(f);
+        let handles: Vec<_> = (0..threads)
+            .map(|i| {
+                let f = f.clone();
+                std::thread::spawn(move || f(i))
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_adder_under_load() {
+        let adder: Adder<i64> = Adder::new();
+        let stop = Arc::new(std::sync::atomic::AtomicBool::new(false));
+
+        // 只有加正数时，读到的值应该单调不减
+        let reader = {
+            let adder = adder.clone();
+            let stop = stop.clone();
+            std::thread::spawn(move || {
+                let mut last = 0;
+                while !stop.load(std::sync::atomic::Ordering::Relaxed) {
+                    let value = adder.get_value();
+                    assert!(value >= last, "{} < {}", value, last);
+                    last = value;
+                }
+            })
+        };
+
+        let writer = adder.clone();
+        run_threads(16, move |i| {
+            for j in 0..100_000 {
+                writer.add((i + j % 3) as i64);
+            }
+        });
+        stop.store(true, std::sync::atomic::Ordering::Relaxed);
+        reader.join().unwrap();
+
+        let expected: i64 = (0..16)
+            .map(|i| (0..100_000).map(|j| (i + j % 3) as i64).sum::<i64>())
+            .sum();
+        assert_eq!(adder.get_value(), expected);
+
+        let float_adder: Adder<f64> = Adder::new();
+        let writer = float_adder.clone();
+        run_threads(8, move |_| {
+            for _ in 0..10_000 {
+                writer.add(0.5);
+            }
+        });
+        assert_eq!(float_adder.get_value(), 8.0 * 10_000.0 * 0.5);
+    }
+
+    #[test]
+    fn test_maxer_miner_under_load() {
+        let maxer = Maxer::new(i64::MIN);
+        let miner = Miner::new(i64::MAX);
+        let (max_writer, min_writer) = (maxer.clone(), miner.clone());
+        run_threads(16, move |i| {
+            for j in 0..50_000i64 {
+                let value = (j * 7919 + i as i64 * 104_729) % 1_000_003 - 500_000;
+                max_writer.add(value);
+                min_writer.add(value);
+            }
+        });
+
+        let values = (0..16i64)
+            .flat_map(|i| (0..50_000i64).map(move |j| (j * 7919 + i * 104_729) % 1_000_003 - 500_000));
+        let (max, min) = (values.clone().max().unwrap(), values.min().unwrap());
+        assert_eq!(maxer.get_value(), max);
+        assert_eq!(miner.get_value(), min);
+
+        assert_eq!(maxer.reset(), max);
+        assert_eq!(maxer.get_value(), i64::MIN);
+        assert_eq!(miner.reset(), min);
+        assert_eq!(miner.get_value(), i64::MAX);
+    }
+
+    #[test]
+    fn test_reset_under_load() {
+        let adder: Adder<i64> = Adder::new();
+        let stop = Arc::new(std::sync::atomic::AtomicBool::new(false));
+
+        // 并发地不断重置，重置取走的值加上最终剩余的值应等于写入的总和
+        let resetter = {
+            let adder = adder.clone();
+            let stop = stop.clone();
+            std::thread::spawn(move || {
+                let mut taken = 0;
+                while !stop.load(std::sync::atomic::Ordering::Relaxed) {
+                    taken += adder.reset();
+                }
+                taken
+            })
+        };
+
+        let writer = adder.clone();
+        run_threads(8, move |_| {
+            for _ in 0..100_000 {
+                writer.add(1);
+            }
+        });
+        stop.store(true, std::sync::atomic::Ordering::Relaxed);
+        let taken = resetter.join().unwrap();
+
+        assert_eq!(taken + adder.get_value(), 8 * 100_000);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,420 @@
+// Copyright 2025 KenForever1
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! 内置的HTTP服务，类似brpc的builtin service，用于查看暴露的变量
+//!
+//! 支持以下路径：
+//!   - `/vars`：列出所有变量，每行`name : value`
+//!   - `/vars/<wildcards>`：只列出匹配的变量，URL中用`$`代替`?`，以`!`开头的通配符表示排除
+//!   - `/vars?series`、`/vars/<wildcards>?series`：以JSON输出变量的历史数据
+//!   - `/metrics`：Prometheus文本格式
+
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::export::{escape_json, prometheus};
+use crate::variable::{collect_exposed, dump_exposed, DumpOptions, SeriesOptions};
+
+/// 请求头的最大长度
+const MAX_REQUEST_HEADER: usize = 8192;
+/// 读取请求的超时时间
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+/// 拒绝连接时写出响应的超时时间
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+/// 没有新连接时检查是否停止的间隔
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(20);
+/// 同时处理的最大连接数，超过时直接返回503
+pub const MAX_CONNECTIONS: usize = 16;
+
+/// 运行中的内置服务，销毁时停止服务
+pub struct BuiltinServer {
+    /// 实际监听的地址
+    local_addr: SocketAddr,
+    /// 是否已经停止
+    stopped: Arc<AtomicBool>,
+    /// 接受连接的线程
+    thread: Option<JoinHandle<()>>,
+}
+
+impl BuiltinServer {
+    /// 获取实际监听的地址，监听端口0时可以由此得到分配的端口
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// 停止服务并等待接受连接的线程退出，最多等待一个检查间隔
+    pub fn stop(&mut self) {
+        if self.stopped.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for BuiltinServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// 在`addr`上启动内置服务
+///
+/// 监听socket是非阻塞的，接受连接的线程定期检查是否停止；
+/// 每个连接由单独的线程处理，同时最多处理[`MAX_CONNECTIONS`]个连接
+pub fn start_builtin_server<A: ToSocketAddrs>(addr: A) -> io::Result<BuiltinServer> {
+    let listener = TcpListener::bind(addr)?;
+    listener.set_nonblocking(true)?;
+    let local_addr = listener.local_addr()?;
+    let stopped = Arc::new(AtomicBool::new(false));
+
+    let thread_stopped = stopped.clone();
+    let thread = thread::Builder::new()
+        .name("bvar_builtin_server".to_string())
+        .spawn(move || accept_loop(listener, &thread_stopped))?;
+
+    Ok(BuiltinServer {
+        local_addr,
+        stopped,
+        thread: Some(thread),
+    })
+}
+
+/// 接受连接直到`stopped`被设置
+fn accept_loop(listener: TcpListener, stopped: &AtomicBool) {
+    let active = Arc::new(AtomicUsize::new(0));
+    while !stopped.load(Ordering::SeqCst) {
+        let stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(ACCEPT_POLL_INTERVAL);
+                continue;
+            }
+            Err(e) => {
+                log::warn!("Builtin server fail to accept: {}", e);
+                thread::sleep(ACCEPT_POLL_INTERVAL);
+                continue;
+            }
+        };
+        if let Err(e) = stream.set_nonblocking(false) {
+            log::debug!("Builtin server connection error: {}", e);
+            continue;
+        }
+
+        if active.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+            active.fetch_sub(1, Ordering::SeqCst);
+            log::warn!("Builtin server reject connection, too many connections");
+            let _ = reject_connection(stream);
+            continue;
+        }
+        let guard = ActiveConnection(active.clone());
+        let spawned = thread::Builder::new()
+            .name("bvar_builtin_conn".to_string())
+            .spawn(move || {
+                let _guard = guard;
+                if let Err(e) = handle_connection(stream) {
+                    log::debug!("Builtin server connection error: {}", e);
+                }
+            });
+        if let Err(e) = spawned {
+            log::warn!("Builtin server fail to spawn connection thread: {}", e);
+        }
+    }
+}
+
+/// 正在处理的连接，销毁时减少连接计数
+struct ActiveConnection(Arc<AtomicUsize>);
+
+impl Drop for ActiveConnection {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// 连接数达到上限时在接受连接的线程中直接返回503，不读取请求
+fn reject_connection(mut stream: TcpStream) -> io::Result<()> {
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    write_response(&mut stream, &Response::error("503 Service Unavailable"), true)
+}
+
+/// 一个HTTP响应
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    fn ok(content_type: &'static str, body: String) -> Self {
+        Self {
+            status: "200 OK",
+            content_type,
+            body,
+        }
+    }
+
+    fn error(status: &'static str) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: format!("{}\n", status),
+        }
+    }
+}
+
+/// 处理一个连接上的一次请求
+fn handle_connection(mut stream: TcpStream) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+
+    let mut buf = Vec::with_capacity(1024);
+    let mut chunk = [0u8; 1024];
+    while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut chunk)?;
+        if n == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if buf.len() > MAX_REQUEST_HEADER {
+            break;
+        }
+    }
+
+    let request = String::from_utf8_lossy(&buf);
+    let mut parts = request.lines().next().unwrap_or("").split_whitespace();
+    let method = parts.next().unwrap_or("");
+    let target = parts.next().unwrap_or("");
+
+    let response = if buf.len() > MAX_REQUEST_HEADER {
+        Response::error("431 Request Header Fields Too Large")
+    } else if method != "GET" && method != "HEAD" {
+        Response::error("405 Method Not Allowed")
+    } else {
+        route(target)
+    };
+
+    write_response(&mut stream, &response, method != "HEAD")
+}
+
+/// 写出响应并关闭连接
+fn write_response(stream: &mut TcpStream, response: &Response, with_body: bool) -> io::Result<()> {
+    let mut out = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    )
+    .into_bytes();
+    if with_body {
+        out.extend_from_slice(response.body.as_bytes());
+    }
+    stream.write_all(&out)?;
+    stream.flush()?;
+    let _ = stream.shutdown(Shutdown::Both);
+    Ok(())
+}
+
+/// 根据请求路径生成响应
+fn route(target: &str) -> Response {
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path, query),
+        None => (target, ""),
+    };
+    let series = query.split('&').any(|item| item == "series");
+
+    if path == "/metrics" {
+        let body = prometheus::render(&DumpOptions::new());
+        return Response::ok("text/plain; version=0.0.4; charset=utf-8", body);
+    }
+
+    let filter = if path == "/vars" || path == "/vars/" {
+        String::new()
+    } else if let Some(filter) = path.strip_prefix("/vars/") {
+        percent_decode(filter)
+    } else {
+        return Response::error("404 Not Found");
+    };
+
+    let options = DumpOptions::from_filter(&filter).with_question_mark('$');
+    if series {
+        Response::ok("application/json", describe_series(&options))
+    } else {
+        let mut body = String::new();
+        dump_exposed(&options, &mut |name: &str, description: &str| {
+            let _ = writeln!(body, "{} : {}", name, description);
+            true
+        });
+        Response::ok("text/plain; charset=utf-8", body)
+    }
+}
+
+/// 以JSON对象输出匹配变量的历史数据，键为变量名
+fn describe_series(options: &DumpOptions) -> String {
+    let series_options = SeriesOptions::default();
+    let mut body = String::from("{");
+    let mut first = true;
+    let mut series = String::new();
+    for (name, var) in collect_exposed(options) {
+        series.clear();
+        if !var.describe_series(&mut series, &series_options) {
+            continue;
+        }
+        if !first {
+            body.push(',');
+        }
+        first = false;
+        let _ = write!(body, "\"{}\":{}", escape_json(&name), series);
+    }
+    body.push('}');
+    body
+}
+
+/// 解码URL中的`%XX`
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = |b: u8| (b as char).to_digit(16);
+            if let (Some(hi), Some(lo)) = (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                out.push((hi * 16 + lo) as u8);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::status::Status;
+    use crate::variable::{ExposeError, Exposure, Variable};
+    use std::fmt;
+
+    /// 请求内置服务，返回状态行和响应体
+    fn get(addr: SocketAddr, target: &str) -> (String, String) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", target).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        (head.lines().next().unwrap().to_string(), body.to_string())
+    }
+
+    #[derive(Clone)]
+    struct SeriesVar {
+        exposure: Exposure,
+    }
+
+    impl Variable for SeriesVar {
+        fn describe(&self, f: &mut String, _quote_string: bool) -> bool {
+            f.push('1');
+            true
+        }
+
+        fn describe_series(&self, f: &mut dyn fmt::Write, _options: &SeriesOptions) -> bool {
+            let _ = write!(f, "[1,2,3]");
+            true
+        }
+
+        fn expose_impl(&self, prefix: &str, name: &str) -> Result<(), ExposeError> {
+            self.default_expose_impl(prefix, name)
+        }
+
+        fn exposure(&self) -> Option<&Exposure> {
+            Some(&self.exposure)
+        }
+    }
+
+    #[test]
+    fn test_builtin_server() {
+        let _get_qps = Status::with_name(10, "server_test_get_qps").unwrap();
+        let _set_qps = Status::with_name(20, "server_test_set_qps").unwrap();
+        let _debug = Status::with_name("on".to_string(), "server_test_debug").unwrap();
+        let series_var = SeriesVar { exposure: Exposure::new() };
+        series_var.expose("server_test_series").unwrap();
+
+        let mut server = start_builtin_server("127.0.0.1:0").unwrap();
+        let addr = server.local_addr();
+
+        let (status, body) = get(addr, "/vars");
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert!(body.contains("server_test_get_qps : 10\n"));
+        assert!(body.contains("server_test_debug : \"on\"\n"));
+
+        let (_, body) = get(addr, "/vars/server_test_$et_qps;!server_test_s*");
+        assert_eq!(body, "server_test_get_qps : 10\n");
+        let (_, body) = get(addr, "/vars/server_test_*_qps%3B!*get*");
+        assert_eq!(body, "server_test_set_qps : 20\n");
+
+        let (_, body) = get(addr, "/vars/server_test_*?series");
+        assert_eq!(body, "{\"server_test_series\":[1,2,3]}");
+
+        let (_, body) = get(addr, "/metrics");
+        assert!(body.contains("# TYPE server_test_get_qps gauge\nserver_test_get_qps 10\n"));
+
+        let (status, _) = get(addr, "/unknown");
+        assert_eq!(status, "HTTP/1.1 404 Not Found");
+
+        server.stop();
+        assert!(TcpStream::connect(addr).is_err());
+    }
+
+    #[test]
+    fn test_builtin_server_limits() {
+        // 监听所有地址时也可以停止
+        let mut server = start_builtin_server("0.0.0.0:0").unwrap();
+        let addr = SocketAddr::from(([127, 0, 0, 1], server.local_addr().port()));
+
+        // 不发送请求的连接占满连接数后，新的连接直接收到503
+        let idle: Vec<TcpStream> = (0..MAX_CONNECTIONS)
+            .map(|_| TcpStream::connect(addr).unwrap())
+            .collect();
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        let status = loop {
+            let (status, _) = get(addr, "/vars");
+            if status.contains("503") || std::time::Instant::now() > deadline {
+                break status;
+            }
+            thread::sleep(ACCEPT_POLL_INTERVAL);
+        };
+        assert_eq!(status, "HTTP/1.1 503 Service Unavailable");
+
+        // 空闲连接关闭后恢复服务
+        drop(idle);
+        let deadline = std::time::Instant::now() + Duration::from_secs(10);
+        while get(addr, "/vars").0 != "HTTP/1.1 200 OK" {
+            assert!(std::time::Instant::now() < deadline);
+            thread::sleep(ACCEPT_POLL_INTERVAL);
+        }
+        server.stop();
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("a%3Bb%2a"), "a;b*");
+        assert_eq!(percent_decode("bad%zz%4"), "bad%zz%4");
+        assert_eq!(escape_json("a\"b\\\n"), "a\\\"b\\\\\\u000a");
+    }
+}
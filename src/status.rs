@@ -15,48 +15,49 @@
 //! 实现运行时可修改的状态变量
 
 use std::fmt;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use num_traits::{FromPrimitive, NumOps};
 use parking_lot::RwLock;
 use std::fmt::Write;
-use crate::variable::Variable;
-use std::cell::UnsafeCell;
+use crate::detail::sampler::{SeriesSampler, SharedSampler, SharedSeries};
+use crate::detail::series::Series;
+use crate::reducer::{AddTo, MinusFrom, ReducerTrait};
+use crate::variable::{ExposeError, Exposure, SeriesOptions, Variable};
+use crate::window::WindowSource;
 
 /// 表示可变的状态
+#[derive(Clone)]
 pub struct Status<T> {
     /// 内部值
-    value: RwLock<T>,
-    /// 变量名称
-    name: UnsafeCell<String>,
-    /// 是否已经被暴露
-    exposed: AtomicBool,
+    value: Arc<RwLock<T>>,
+    /// 历史数据，默认不开启
+    series: SharedSeries,
+    /// 暴露信息
+    exposure: Exposure,
 }
 
-// 手动实现线程安全 - 我们确保对UnsafeCell的访问是安全的
-unsafe impl<T> Send for Status<T> {}
-unsafe impl<T> Sync for Status<T> {}
-
 impl<T: Clone + fmt::Display + Send + Sync + 'static> Status<T> {
     /// 创建新的状态变量
     pub fn new(value: T) -> Self {
         Self {
-            value: RwLock::new(value),
-            name: UnsafeCell::new(String::new()),
-            exposed: AtomicBool::new(false),
+            value: Arc::new(RwLock::new(value)),
+            series: SharedSeries::new(),
+            exposure: Exposure::new(),
         }
     }
     
-    /// 用名称创建
-    pub fn with_name(value: T, name: &str) -> Self {
+    /// 用名称创建，名称不可用时返回错误
+    pub fn with_name(value: T, name: &str) -> Result<Self, ExposeError> {
         let status = Self::new(value);
-        let _ = status.expose(name);
-        status
+        status.expose(name)?;
+        Ok(status)
     }
     
-    /// 用前缀和名称创建
-    pub fn with_prefix_name(value: T, prefix: &str, name: &str) -> Self {
+    /// 用前缀和名称创建，名称不可用时返回错误
+    pub fn with_prefix_name(value: T, prefix: &str, name: &str) -> Result<Self, ExposeError> {
         let status = Self::new(value);
-        let _ = status.expose_as(prefix, name);
-        status
+        status.expose_as(prefix, name)?;
+        Ok(status)
     }
     
     /// 获取当前值
@@ -68,6 +69,28 @@ impl<T: Clone + fmt::Display + Send + Sync + 'static> Status<T> {
     pub fn set_value(&self, value: T) {
         *self.value.write() = value;
     }
+
+    /// 开启历史数据
+    pub fn with_series(self) -> Self
+    where
+        T: NumOps + FromPrimitive + Default + fmt::Debug,
+    {
+        self.enable_series();
+        self
+    }
+
+    /// 开启历史数据，每秒记录一次当前值，合并为更粗的粒度时取平均值
+    ///
+    /// 对所有克隆以及已经暴露的变量生效
+    pub fn enable_series(&self)
+    where
+        T: NumOps + FromPrimitive + Default + fmt::Debug,
+    {
+        let value = self.value.clone();
+        self.series.enable(|| {
+            SeriesSampler::new(Series::averaged(AddTo::default()), move || value.read().clone())
+        });
+    }
 }
 
 impl<T: Clone + fmt::Display + Send + Sync + 'static> Variable for Status<T> {
@@ -81,53 +104,162 @@ impl<T: Clone + fmt::Display + Send + Sync + 'static> Variable for Status<T> {
         true
     }
     
-    fn expose_impl(&self, prefix: &str, name: &str) -> i32 {
-        // 更新内部名称
-        let mut full_name = String::new();
-        if !prefix.is_empty() {
-            full_name.push_str(prefix);
-            full_name.push('_');
+    fn expose_impl(&self, prefix: &str, name: &str) -> Result<(), ExposeError> {
+        self.default_expose_impl(prefix, name)
+    }
+
+    fn exposure(&self) -> Option<&Exposure> {
+        Some(&self.exposure)
+    }
+
+    fn describe_series(&self, f: &mut dyn fmt::Write, options: &SeriesOptions) -> bool {
+        self.series.describe(self, f, options)
+    }
+}
+
+/// 读取时才计算值的状态，类似bvar的`PassiveStatus`
+///
+/// 适合队列长度、缓存大小这类读取时计算更便宜的值，每次`describe`都会调用`getter`：
+///
+/// ```ignore
+/// let queue_len = PassiveStatus::with_name("task_queue_len", move || queue.len() as i64)?;
+/// ```
+///
+/// 数值类型的`PassiveStatus`可以作为[`Window`]或[`PerSecond`]的数据源，与bvar一样把
+/// `getter`的返回值视为累计值，窗口内的值为最新值与窗口起点的值之差：
+///
+/// ```ignore
+/// let processed = PassiveStatus::new(move || counter.load(Ordering::Relaxed) as i64);
+/// let processed_qps = PerSecond::with_name("task_processed_qps", &processed)?;
+/// ```
+///
+/// [`Window`]: crate::window::Window
+/// [`PerSecond`]: crate::window::PerSecond
+pub struct PassiveStatus<T> {
+    /// 计算当前值的函数
+    getter: Arc<dyn Fn() -> T + Send + Sync>,
+    /// 窗口使用的采样器
+    sampler: SharedSampler,
+    /// 暴露信息
+    exposure: Exposure,
+}
+
+impl<T> Clone for PassiveStatus<T> {
+    fn clone(&self) -> Self {
+        Self {
+            getter: self.getter.clone(),
+            sampler: self.sampler.clone(),
+            exposure: self.exposure.clone(),
         }
-        full_name.push_str(name);
-        
-        // 将自己暴露出去
-        let result = <Status<T> as Variable>::default_expose_impl(self, prefix, name);
-        if result == 0 {
-            // 仅在成功时更新名称
-            self.exposed.store(true, Ordering::Relaxed);
-            // 使用UnsafeCell安全地更新内部状态
-            unsafe {
-                *self.name.get() = full_name;
-            }
+    }
+}
+
+impl<T: fmt::Display + Send + Sync + 'static> PassiveStatus<T> {
+    /// 创建新的状态变量
+    pub fn new<F>(getter: F) -> Self
+    where
+        F: Fn() -> T + Send + Sync + 'static,
+    {
+        Self {
+            getter: Arc::new(getter),
+            sampler: SharedSampler::new(),
+            exposure: Exposure::new(),
         }
-        result
     }
-    
-    fn name(&self) -> String {
-        unsafe { (*self.name.get()).clone() }
+
+    /// 用名称创建，名称不可用时返回错误
+    pub fn with_name<F>(name: &str, getter: F) -> Result<Self, ExposeError>
+    where
+        F: Fn() -> T + Send + Sync + 'static,
+    {
+        let status = Self::new(getter);
+        status.expose(name)?;
+        Ok(status)
     }
-    
-    fn hide(&self) -> bool {
-        let result = Variable::default_hide(self);
-        if result {
-            self.exposed.store(false, Ordering::Relaxed);
+
+    /// 用前缀和名称创建，名称不可用时返回错误
+    pub fn with_prefix_name<F>(prefix: &str, name: &str, getter: F) -> Result<Self, ExposeError>
+    where
+        F: Fn() -> T + Send + Sync + 'static,
+    {
+        let status = Self::new(getter);
+        status.expose_as(prefix, name)?;
+        Ok(status)
+    }
+
+    /// 调用`getter`计算当前值
+    pub fn get_value(&self) -> T {
+        (self.getter)()
+    }
+}
+
+impl<T: fmt::Display + Send + Sync + 'static> Variable for PassiveStatus<T> {
+    fn describe(&self, f: &mut String, quote_string: bool) -> bool {
+        let value = self.get_value();
+        if quote_string && std::any::TypeId::of::<T>() == std::any::TypeId::of::<String>() {
+            let _ = write!(f, "\"{}\"", value);
+        } else {
+            let _ = write!(f, "{}", value);
         }
-        result
+        true
     }
-    
-    fn is_hidden(&self) -> bool {
-        !self.exposed.load(Ordering::Relaxed)
+
+    fn expose_impl(&self, prefix: &str, name: &str) -> Result<(), ExposeError> {
+        self.default_expose_impl(prefix, name)
+    }
+
+    fn exposure(&self) -> Option<&Exposure> {
+        Some(&self.exposure)
+    }
+}
+
+impl<T> ReducerTrait<T, AddTo<T>> for PassiveStatus<T>
+where
+    T: NumOps + Clone + fmt::Display + Send + Sync + 'static,
+{
+    fn get_value(&self) -> T {
+        self.get_value()
+    }
+
+    /// `getter`无法被重置，只返回当前值。窗口使用可逆的`MinusFrom`，不会调用它
+    fn reset(&self) -> T {
+        self.get_value()
+    }
+
+    fn op(&self) -> AddTo<T> {
+        AddTo::default()
+    }
+}
+
+impl<T> WindowSource for PassiveStatus<T>
+where
+    T: NumOps + Clone + fmt::Display + Send + Sync + 'static,
+{
+    type Value = T;
+    type Op = AddTo<T>;
+    type InvOp = MinusFrom<T>;
+
+    fn inv_op(&self) -> MinusFrom<T> {
+        MinusFrom::default()
+    }
+
+    fn shared_sampler(&self) -> &SharedSampler {
+        &self.sampler
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::detail::clock::MockClock;
+    use crate::window::{PerSecond, Window};
+    use std::sync::atomic::{AtomicI64, Ordering};
+    use std::time::Duration;
     
     #[test]
     fn test_status() {
         let status = Status::new(1);
-        let _ = status.expose("test");
+        let _ = status.expose("status_test");
         let value = status.get_value();
         assert_eq!(value, 1);
         let _ = status.hide();
@@ -139,4 +271,46 @@ mod tests {
         let value = status.get_value();
         assert_eq!(value, 2);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_passive_status() {
+        let counter = Arc::new(AtomicI64::new(0));
+        let status = {
+            let counter = counter.clone();
+            PassiveStatus::new(move || counter.load(Ordering::Relaxed))
+        };
+        assert_eq!(status.get_description(), "0");
+        counter.store(5, Ordering::Relaxed);
+        assert_eq!(status.get_value(), 5);
+
+        let name = PassiveStatus::new(|| "ready".to_string());
+        let mut quoted = String::new();
+        name.describe(&mut quoted, true);
+        assert_eq!(quoted, "\"ready\"");
+    }
+
+    #[test]
+    fn test_passive_status_window() {
+        let clock = Arc::new(MockClock::new());
+        let counter = Arc::new(AtomicI64::new(0));
+        let status = {
+            let counter = counter.clone();
+            PassiveStatus::new(move || counter.load(Ordering::Relaxed))
+        };
+
+        // 返回值被视为累计值，窗口内的值是最新值与窗口起点的值之差
+        let sum_in_2s = Window::with_clock(&status, 2, clock.clone());
+        let qps = PerSecond::with_clock(&status, 2, clock.clone());
+        for second in 1..=4 {
+            counter.fetch_add(second * 10, Ordering::Relaxed);
+            clock.advance(Duration::from_secs(1));
+            sum_in_2s.take_sample();
+            qps.take_sample();
+        }
+        assert_eq!(sum_in_2s.get_value(), Some(70));
+        assert_eq!(sum_in_2s.get_description(), "70");
+        assert_eq!(qps.get_value(), 35.0);
+        // 读取不会修改getter的状态
+        assert_eq!(status.get_value(), 100);
+    }
+}
@@ -15,101 +15,338 @@
 //! 变量的基础定义
 
 use dashmap::DashMap;
+use dashmap::mapref::entry::Entry;
 use once_cell::sync::Lazy;
-use std::ptr;
+use parking_lot::{Mutex, RwLock};
+use regex::Regex;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+use crate::detail::wildcard::WildcardMatcher;
+use crate::export::{MetricKind, MetricSample};
 
 /// 存储所有暴露变量的全局表
-static EXPOSED_VARS: Lazy<DashMap<String, VarEntry>> = Lazy::new(|| DashMap::new());
+///
+/// DashMap的分片锁不会中毒，某个线程持有分片时panic不会影响之后的暴露和查询
+static EXPOSED_VARS: Lazy<DashMap<String, VarEntry>> = Lazy::new(DashMap::new);
+
+/// 名称冲突时的处理策略
+static CONFLICT_POLICY: AtomicU8 = AtomicU8::new(ConflictPolicy::Warn as u8);
+
+/// 暴露时是否把名称规范化为小写下划线形式
+static NORMALIZE_NAMES: AtomicBool = AtomicBool::new(true);
 
 struct VarEntry {
-    var_ptr: usize, // 存储变量的指针地址，用于比较身份
-    type_id: std::any::TypeId, // 存储类型ID
+    /// 变量的共享句柄，变量对象本身持有强引用
+    var: Weak<dyn Variable>,
+}
+
+/// 暴露变量失败的原因
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExposeError {
+    /// 名称已被其他变量使用
+    NameConflict(String),
+    /// 名称为空
+    EmptyName,
+    /// 名称中含有空白、控制字符或通配符
+    InvalidCharacters(String),
+    /// 变量没有登记信息，不支持暴露
+    NotExposable,
+}
+
+impl fmt::Display for ExposeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExposeError::NameConflict(name) => write!(f, "variable name `{}` is already exposed", name),
+            ExposeError::EmptyName => write!(f, "variable name is empty"),
+            ExposeError::InvalidCharacters(name) => {
+                write!(f, "variable name `{}` contains invalid characters", name)
+            }
+            ExposeError::NotExposable => write!(f, "variable can not be exposed"),
+        }
+    }
+}
+
+impl std::error::Error for ExposeError {}
+
+/// 暴露变量时名称冲突的处理策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// 通过log输出警告并返回错误
+    Warn = 0,
+    /// debug构建中直接panic，release构建中同`Warn`
+    PanicInDebug = 1,
+    /// 自动在名称后追加`_1`、`_2`等后缀直到不冲突
+    AutoSuffix = 2,
+}
+
+/// 设置名称冲突的处理策略，对之后所有的暴露生效
+pub fn set_conflict_policy(policy: ConflictPolicy) {
+    CONFLICT_POLICY.store(policy as u8, Ordering::Relaxed);
+}
+
+/// 获取名称冲突的处理策略
+pub fn conflict_policy() -> ConflictPolicy {
+    match CONFLICT_POLICY.load(Ordering::Relaxed) {
+        1 => ConflictPolicy::PanicInDebug,
+        2 => ConflictPolicy::AutoSuffix,
+        _ => ConflictPolicy::Warn,
+    }
+}
+
+/// 设置暴露时是否把名称规范化为小写下划线形式，默认开启
+///
+/// 关闭后名称会原样使用，但仍然需要通过合法性检查
+pub fn set_normalize_names(enabled: bool) {
+    NORMALIZE_NAMES.store(enabled, Ordering::Relaxed);
+}
+
+/// 暴露时是否规范化名称
+pub fn normalize_names() -> bool {
+    NORMALIZE_NAMES.load(Ordering::Relaxed)
+}
+
+/// 把`src`转换为小写下划线形式并追加到`name`后面
+///
+/// 与bvar的规则一致：大写字母转为小写，单词边界的大写字母前插入`_`，
+/// 其它非字母数字的字符变为`_`，且不会出现连续的`_`。
+/// 例如`HttpServer.Requests`转换为`http_server_requests`。
+pub fn to_underscored_name(name: &mut String, src: &str) {
+    let mut prev: Option<char> = None;
+    for c in src.chars() {
+        if c.is_ascii_alphabetic() {
+            if c.is_ascii_uppercase() {
+                let after_upper = prev.is_some_and(|p| p.is_ascii_uppercase());
+                if prev.is_some() && !after_upper && !name.ends_with('_') {
+                    name.push('_');
+                }
+                name.push(c.to_ascii_lowercase());
+            } else {
+                name.push(c);
+            }
+        } else if c.is_ascii_digit() {
+            name.push(c);
+        } else if !name.ends_with('_') {
+            name.push('_');
+        }
+        prev = Some(c);
+    }
+}
+
+/// 拼接前缀和名称，得到暴露到全局表中的完整名称
+fn make_full_name(prefix: &str, name: &str) -> String {
+    if !normalize_names() {
+        return if prefix.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}_{}", prefix, name)
+        };
+    }
+
+    let mut full_name = String::with_capacity((prefix.len() + name.len()) * 5 / 4);
+    if !prefix.is_empty() {
+        to_underscored_name(&mut full_name, prefix);
+        if !full_name.is_empty() && !full_name.ends_with('_') {
+            full_name.push('_');
+        }
+    }
+    to_underscored_name(&mut full_name, name);
+    full_name
+}
+
+/// 检查名称是否可以被暴露
+fn check_name(name: &str) -> Result<(), ExposeError> {
+    if name.is_empty() {
+        return Err(ExposeError::EmptyName);
+    }
+    let invalid = |c: char| c.is_whitespace() || c.is_control() || matches!(c, ';' | ',' | '*' | '?');
+    if name.chars().any(invalid) {
+        return Err(ExposeError::InvalidCharacters(name.to_string()));
+    }
+    Ok(())
+}
+
+/// 变量在全局表中的登记信息，每个变量对象各自持有一份
+///
+/// 暴露时会把变量的一个克隆（与原变量共享数据）放入`Arc`中，
+/// 全局表只保存它的弱引用，因此变量对象被移动后全局表依然有效。
+/// 登记信息被销毁时会自动隐藏变量。
+#[derive(Default)]
+pub struct Exposure {
+    /// 暴露的名称，未暴露时为空
+    name: RwLock<String>,
+    /// 登记到全局表中的共享句柄
+    handle: Mutex<Option<Arc<dyn Variable>>>,
+}
+
+impl Exposure {
+    /// 创建未暴露的登记信息
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 以`full_name`将`handle`登记到全局表中，之前的名称会先被隐藏
+    ///
+    /// 名称冲突时按照[`conflict_policy`]处理
+    pub fn expose(&self, full_name: String, handle: Arc<dyn Variable>) -> Result<(), ExposeError> {
+        if let Err(e) = check_name(&full_name) {
+            log::warn!("Fail to expose variable: {}", e);
+            return Err(e);
+        }
+        self.hide();
+
+        let policy = conflict_policy();
+        let mut name = full_name.clone();
+        let mut suffix = 0;
+        loop {
+            if let Entry::Vacant(entry) = EXPOSED_VARS.entry(name.clone()) {
+                entry.insert(VarEntry {
+                    var: Arc::downgrade(&handle),
+                });
+                *self.handle.lock() = Some(handle);
+                *self.name.write() = name;
+                return Ok(());
+            }
+
+            // 名称冲突
+            match policy {
+                ConflictPolicy::AutoSuffix => {
+                    suffix += 1;
+                    name = format!("{}_{}", full_name, suffix);
+                }
+                ConflictPolicy::PanicInDebug if cfg!(debug_assertions) => {
+                    panic!("variable name `{}` is already exposed", name);
+                }
+                _ => {
+                    let e = ExposeError::NameConflict(name);
+                    log::warn!("Fail to expose variable: {}", e);
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    /// 从全局表中移除自己，返回是否真的移除了
+    pub fn hide(&self) -> bool {
+        let handle = match self.handle.lock().take() {
+            Some(handle) => handle,
+            None => return false,
+        };
+        let name = std::mem::take(&mut *self.name.write());
+
+        // 比较句柄地址确认是同一个变量
+        let self_ptr = Arc::as_ptr(&handle) as *const ();
+        EXPOSED_VARS
+            .remove_if(&name, |_, entry| entry.var.as_ptr() as *const () == self_ptr)
+            .is_some()
+    }
+
+    /// 获取暴露的名称
+    pub fn name(&self) -> String {
+        self.name.read().clone()
+    }
+}
+
+impl Drop for Exposure {
+    /// 变量销毁时自动从全局表中移除，名称可以被重新使用
+    fn drop(&mut self) {
+        self.hide();
+    }
+}
+
+impl fmt::Debug for Exposure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Exposure").field("name", &*self.name.read()).finish()
+    }
+}
+
+impl Clone for Exposure {
+    /// 克隆出的变量与原变量共享数据，但不继承暴露状态
+    fn clone(&self) -> Self {
+        Self::default()
+    }
 }
 
 /// 变量基础特性
 pub trait Variable: Send + Sync  where Self: 'static{
     /// 将变量描述为字符串
     fn describe(&self, f: &mut String, quote_string: bool) -> bool;
-    
+
     /// 获取变量的描述
     fn get_description(&self) -> String {
         let mut buf = String::new();
         let _ = self.describe(&mut buf, false);
         buf
     }
-    
+
     /// 暴露此变量，使其可以被查询
-    fn expose(&self, name: &str) -> i32 {
+    fn expose(&self, name: &str) -> Result<(), ExposeError> {
         self.expose_impl("", name)
     }
-    
+
     /// 使用前缀暴露此变量
-    fn expose_as(&self, prefix: &str, name: &str) -> i32 {
+    fn expose_as(&self, prefix: &str, name: &str) -> Result<(), ExposeError> {
         self.expose_impl(prefix, name)
     }
-    
+
+    /// 获取变量的登记信息，未实现时变量无法被暴露
+    fn exposure(&self) -> Option<&Exposure> {
+        None
+    }
+
     /// 隐藏此变量，使其不能被查询
     fn default_hide(&self) -> bool {
-        // 从全局表中移除自己
-        let var_name = self.name();
-        if var_name.is_empty() {
-            return false;
+        match self.exposure() {
+            Some(exposure) => exposure.hide(),
+            None => false,
         }
-        
-        if let Some((_, entry)) = EXPOSED_VARS.remove(&var_name) {
-            // 比较指针地址确认是同一个变量
-            let self_ptr = ptr::addr_of!(*self) as *const () as usize;
-            if entry.var_ptr == self_ptr && 
-               entry.type_id == std::any::TypeId::of::<Self>() {
-                return true;
-            } else {
-                // 不是同一个变量，放回去
-                let _ = EXPOSED_VARS.insert(var_name, entry);
-            }
-        }
-        
-        false
     }
 
     fn hide(&self) -> bool {
         self.default_hide()
     }
-    
+
     /// 检查变量是否被隐藏
     fn is_hidden(&self) -> bool {
         self.name().is_empty()
     }
-    
+
     /// 获取变量名称
     fn name(&self) -> String {
-        String::new()
+        self.exposure().map(Exposure::name).unwrap_or_default()
     }
-    
-    fn expose_impl(&self, prefix: &str, name: &str) -> i32;
-    /// 实现暴露变量的方法
-    fn default_expose_impl(&self, prefix: &str, name: &str) -> i32 {
-        // 构建完整名称
-        let full_name = if prefix.is_empty() {
-            name.to_string()
-        } else {
-            format!("{}_{}", prefix, name)
-        };
-        
-        // 创建变量条目
-        let self_ptr = ptr::addr_of!(*self) as *const () as usize;
-        let entry = VarEntry {
-            var_ptr: self_ptr,
-            type_id: std::any::TypeId::of::<Self>(),
-        };
-        
-        if EXPOSED_VARS.contains_key(&full_name) {
-            // 名称冲突
-            -1
-        } else {
-            EXPOSED_VARS.insert(full_name, entry);
-            0
+
+    /// 以JSON格式描述变量的历史数据，不支持时返回false
+    fn describe_series(&self, _f: &mut dyn fmt::Write, _options: &SeriesOptions) -> bool {
+        false
+    }
+
+    /// 获取变量导出到监控系统时的类型
+    fn metric_kind(&self) -> MetricKind {
+        MetricKind::Gauge
+    }
+
+    /// 获取变量导出到监控系统时的样本，默认把描述解析为一个数值
+    ///
+    /// 描述不是数值的变量（如字符串状态）不会被导出
+    fn metric_samples(&self) -> Vec<MetricSample> {
+        match self.get_description().trim().parse::<f64>() {
+            Ok(value) => vec![MetricSample::new(value)],
+            Err(_) => Vec::new(),
         }
     }
+
+    fn expose_impl(&self, prefix: &str, name: &str) -> Result<(), ExposeError>;
+    /// 实现暴露变量的方法
+    fn default_expose_impl(&self, prefix: &str, name: &str) -> Result<(), ExposeError>
+    where
+        Self: Sized + Clone,
+    {
+        let exposure = self.exposure().ok_or(ExposeError::NotExposable)?;
+        exposure.expose(make_full_name(prefix, name), Arc::new(self.clone()))
+    }
 }
 
 /// 获取暴露变量的数量
@@ -117,15 +354,252 @@ pub fn count_exposed() -> usize {
     EXPOSED_VARS.len()
 }
 
+/// 按名称获取暴露的变量
+pub fn get_exposed(name: &str) -> Option<Arc<dyn Variable>> {
+    EXPOSED_VARS.get(name).and_then(|entry| entry.var.upgrade())
+}
+
+/// 按名称描述暴露的变量，变量不存在时返回false
+pub fn describe_exposed(name: &str, f: &mut String, quote_string: bool) -> bool {
+    // 先取出句柄再描述，避免在持有全局表的锁时调用变量的代码
+    match get_exposed(name) {
+        Some(var) => var.describe(f, quote_string),
+        None => false,
+    }
+}
+
+/// 列出所有暴露变量的名称，按字典序排列
+pub fn list_exposed() -> Vec<String> {
+    let mut names: Vec<String> = EXPOSED_VARS.iter().map(|entry| entry.key().clone()).collect();
+    names.sort();
+    names
+}
+
+/// 接收被dump的变量
+pub trait Dumper {
+    /// 处理一个变量，返回false时终止dump
+    fn dump(&mut self, name: &str, description: &str) -> bool;
+}
+
+impl<F> Dumper for F
+where
+    F: FnMut(&str, &str) -> bool,
+{
+    fn dump(&mut self, name: &str, description: &str) -> bool {
+        self(name, description)
+    }
+}
+
+/// dump变量时的选项
+#[derive(Debug, Clone)]
+pub struct DumpOptions {
+    /// 是否给字符串类型的值加上引号
+    pub quote_string: bool,
+    /// 通配符中匹配单个字符的符号，在URL中可以用`$`代替`?`
+    pub question_mark: char,
+    /// 白名单通配符，以`;`分隔，为空时匹配所有变量
+    pub white_wildcards: String,
+    /// 黑名单通配符，以`;`分隔
+    pub black_wildcards: String,
+    /// 白名单正则，变量名需要匹配它
+    pub white_regex: Option<Regex>,
+    /// 黑名单正则，匹配它的变量会被跳过
+    pub black_regex: Option<Regex>,
+}
+
+impl Default for DumpOptions {
+    fn default() -> Self {
+        Self {
+            quote_string: true,
+            question_mark: '?',
+            white_wildcards: String::new(),
+            black_wildcards: String::new(),
+            white_regex: None,
+            black_regex: None,
+        }
+    }
+}
+
+impl DumpOptions {
+    /// 创建新的dump选项
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 从过滤串创建选项，以`!`开头的通配符会进入黑名单
+    ///
+    /// 例如`rpc_*_latency*;!*_debug`
+    pub fn from_filter(filter: &str) -> Self {
+        let mut white = Vec::new();
+        let mut black = Vec::new();
+        for item in filter.split([';', ',']).map(str::trim) {
+            match item.strip_prefix('!') {
+                Some(pattern) => black.push(pattern),
+                None if !item.is_empty() => white.push(item),
+                None => {}
+            }
+        }
+        Self::new()
+            .with_white_wildcards(&white.join(";"))
+            .with_black_wildcards(&black.join(";"))
+    }
+
+    /// 设置是否给字符串加引号
+    pub fn with_quote_string(mut self, quote_string: bool) -> Self {
+        self.quote_string = quote_string;
+        self
+    }
+
+    /// 设置匹配单个字符的符号
+    pub fn with_question_mark(mut self, question_mark: char) -> Self {
+        self.question_mark = question_mark;
+        self
+    }
+
+    /// 设置白名单通配符
+    pub fn with_white_wildcards(mut self, wildcards: &str) -> Self {
+        self.white_wildcards = wildcards.to_string();
+        self
+    }
+
+    /// 设置黑名单通配符
+    pub fn with_black_wildcards(mut self, wildcards: &str) -> Self {
+        self.black_wildcards = wildcards.to_string();
+        self
+    }
+
+    /// 设置白名单正则
+    pub fn with_white_regex(mut self, pattern: &str) -> Result<Self, regex::Error> {
+        self.white_regex = Some(Regex::new(pattern)?);
+        Ok(self)
+    }
+
+    /// 设置黑名单正则
+    pub fn with_black_regex(mut self, pattern: &str) -> Result<Self, regex::Error> {
+        self.black_regex = Some(Regex::new(pattern)?);
+        Ok(self)
+    }
+}
+
+/// 根据dump选项过滤变量名
+struct DumpFilter<'a> {
+    options: &'a DumpOptions,
+    white: WildcardMatcher,
+    black: WildcardMatcher,
+}
+
+impl<'a> DumpFilter<'a> {
+    fn new(options: &'a DumpOptions) -> Self {
+        Self {
+            options,
+            white: WildcardMatcher::new(&options.white_wildcards, options.question_mark, true),
+            black: WildcardMatcher::new(&options.black_wildcards, options.question_mark, false),
+        }
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        if !self.white.matches(name) || self.black.matches(name) {
+            return false;
+        }
+        if let Some(regex) = &self.options.white_regex {
+            if !regex.is_match(name) {
+                return false;
+            }
+        }
+        if let Some(regex) = &self.options.black_regex {
+            if regex.is_match(name) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// 按名称顺序取出匹配`options`中过滤条件的变量
+pub fn collect_exposed(options: &DumpOptions) -> Vec<(String, Arc<dyn Variable>)> {
+    let filter = DumpFilter::new(options);
+    list_exposed()
+        .into_iter()
+        .filter(|name| filter.matches(name))
+        // 变量可能在列出后被隐藏
+        .filter_map(|name| get_exposed(&name).map(|var| (name, var)))
+        .collect()
+}
+
+/// 按名称顺序把匹配`options`的变量交给`dumper`
+///
+/// 返回dump的变量数量，`dumper`返回false时返回-1
+pub fn dump_exposed(options: &DumpOptions, dumper: &mut dyn Dumper) -> i32 {
+    let mut count = 0;
+    let mut description = String::new();
+
+    for (name, var) in collect_exposed(options) {
+        description.clear();
+        if !var.describe(&mut description, options.quote_string) {
+            continue;
+        }
+        if !dumper.dump(&name, &description) {
+            return -1;
+        }
+        count += 1;
+    }
+    count
+}
+
+/// 历史数据的粒度
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeriesGranularity {
+    /// 最近60秒，每秒一个数据点
+    Second,
+    /// 最近60分钟，每分钟一个数据点
+    Minute,
+    /// 最近24小时，每小时一个数据点
+    Hour,
+    /// 最近30天，每天一个数据点
+    Day,
+}
+
+impl SeriesGranularity {
+    /// 所有粒度，从细到粗排列
+    pub const ALL: [SeriesGranularity; 4] = [
+        SeriesGranularity::Second,
+        SeriesGranularity::Minute,
+        SeriesGranularity::Hour,
+        SeriesGranularity::Day,
+    ];
+
+    /// 输出时使用的名称
+    pub fn name(&self) -> &'static str {
+        match self {
+            SeriesGranularity::Second => "second",
+            SeriesGranularity::Minute => "minute",
+            SeriesGranularity::Hour => "hour",
+            SeriesGranularity::Day => "day",
+        }
+    }
+
+    /// 相邻数据点的间隔
+    pub fn interval(&self) -> Duration {
+        match self {
+            SeriesGranularity::Second => Duration::from_secs(1),
+            SeriesGranularity::Minute => Duration::from_secs(60),
+            SeriesGranularity::Hour => Duration::from_secs(3600),
+            SeriesGranularity::Day => Duration::from_secs(86400),
+        }
+    }
+}
+
 /// 用于系列数据格式化的选项
 #[derive(Debug, Clone)]
 pub struct SeriesOptions {
-    /// 是否使用固定长度的数据点
+    /// 是否把缺少的数据点补零，使每个粒度的数组长度固定
     pub fixed_length: bool,
-    /// 是否包含描述信息
+    /// 是否包含变量的当前描述
     pub include_description: bool,
-    /// 格式化后数据的最大长度
+    /// 每个粒度最多输出的数据点数量，超出时丢弃最旧的数据点
     pub max_length: Option<usize>,
+    /// 输出哪些粒度，按顺序输出
+    pub granularities: Vec<SeriesGranularity>,
 }
 
 impl Default for SeriesOptions {
@@ -134,6 +608,7 @@ impl Default for SeriesOptions {
             fixed_length: true,
             include_description: true,
             max_length: None,
+            granularities: SeriesGranularity::ALL.to_vec(),
         }
     }
 }
@@ -143,22 +618,284 @@ impl SeriesOptions {
     pub fn new() -> Self {
         Self::default()
     }
-    
+
     /// 设置是否使用固定长度
     pub fn with_fixed_length(mut self, fixed_length: bool) -> Self {
         self.fixed_length = fixed_length;
         self
     }
-    
+
     /// 设置是否包含描述信息
     pub fn with_description(mut self, include_description: bool) -> Self {
         self.include_description = include_description;
         self
     }
-    
+
     /// 设置最大长度
     pub fn with_max_length(mut self, max_length: Option<usize>) -> Self {
         self.max_length = max_length;
         self
     }
-}
\ No newline at end of file
+
+    /// 设置输出的粒度
+    pub fn with_granularities(mut self, granularities: &[SeriesGranularity]) -> Self {
+        self.granularities = granularities.to_vec();
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recorder::IntRecorder;
+    use crate::reducer::{Adder, Maxer};
+    use crate::status::Status;
+    use crate::window::{PerSecond, Window};
+
+    #[test]
+    fn test_describe_exposed() {
+        let status = Status::new(42);
+        assert!(status.expose("variable_test_describe").is_ok());
+
+        let mut buf = String::new();
+        assert!(describe_exposed("variable_test_describe", &mut buf, false));
+        assert_eq!(buf, "42");
+
+        status.set_value(43);
+        let var = get_exposed("variable_test_describe").unwrap();
+        assert_eq!(var.get_description(), "43");
+        assert!(list_exposed().contains(&"variable_test_describe".to_string()));
+
+        assert!(status.hide());
+        assert!(get_exposed("variable_test_describe").is_none());
+        assert!(!describe_exposed("variable_test_describe", &mut String::new(), false));
+    }
+
+    #[test]
+    fn test_dump_exposed() {
+        let names = [
+            "dump_rpc_get_latency",
+            "dump_rpc_set_latency_debug",
+            "dump_rpc_get_qps",
+            "dump_process_cpu",
+        ];
+        let vars: Vec<Status<i32>> = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| Status::with_name(i as i32, name).unwrap())
+            .collect();
+
+        let dump = |options: &DumpOptions| {
+            let mut dumped = Vec::new();
+            let mut dumper = |name: &str, description: &str| {
+                if name.starts_with("dump_") {
+                    dumped.push(format!("{} : {}", name, description));
+                }
+                true
+            };
+            assert!(dump_exposed(options, &mut dumper) >= 0);
+            dumped
+        };
+
+        assert_eq!(
+            dump(&DumpOptions::from_filter("dump_rpc_*_latency*;!*_debug")),
+            vec!["dump_rpc_get_latency : 0"]
+        );
+        assert_eq!(
+            dump(&DumpOptions::new().with_white_wildcards("dump_rpc_???_qps;dump_process_cpu")),
+            vec!["dump_process_cpu : 3", "dump_rpc_get_qps : 2"]
+        );
+        assert_eq!(
+            dump(&DumpOptions::new().with_white_wildcards("dump_rpc_$$$_qps").with_question_mark('$')),
+            vec!["dump_rpc_get_qps : 2"]
+        );
+        assert_eq!(
+            dump(&DumpOptions::new()
+                .with_white_regex("^dump_rpc_(get|set)_")
+                .unwrap()
+                .with_black_regex("qps$")
+                .unwrap()),
+            vec!["dump_rpc_get_latency : 0", "dump_rpc_set_latency_debug : 1"]
+        );
+
+        // dumper返回false时终止
+        let options = DumpOptions::new().with_white_wildcards("dump_*");
+        assert_eq!(dump_exposed(&options, &mut |_: &str, _: &str| false), -1);
+        drop(vars);
+    }
+
+    #[test]
+    fn test_hide_on_drop() {
+        {
+            let status = Status::new(1);
+            let recorder = IntRecorder::new();
+            let adder: Adder<i64> = Adder::new();
+            let maxer = Maxer::new(0);
+            let window = Window::new(&adder, 10);
+            let per_second = PerSecond::new(&adder);
+            assert!(status.expose("drop_test_status").is_ok());
+            assert!(recorder.expose("drop_test_recorder").is_ok());
+            assert!(adder.expose("drop_test_adder").is_ok());
+            assert!(maxer.expose("drop_test_maxer").is_ok());
+            assert!(window.expose("drop_test_window").is_ok());
+            assert!(per_second.expose("drop_test_qps").is_ok());
+            assert_eq!(list_exposed().iter().filter(|name| name.starts_with("drop_test_")).count(), 6);
+        }
+        assert!(list_exposed().iter().all(|name| !name.starts_with("drop_test_")));
+
+        // 名称可以被重新使用
+        let status = Status::new(2);
+        assert!(status.expose("drop_test_status").is_ok());
+    }
+
+    #[test]
+    fn test_expose_then_move() {
+        let make = || {
+            let status = Status::new(7);
+            assert!(status.expose("move_test_status").is_ok());
+            Box::new(status)
+        };
+        let moved = make();
+        assert_eq!(get_exposed("move_test_status").unwrap().get_description(), "7");
+
+        let moved = [*moved];
+        moved[0].set_value(8);
+        assert_eq!(get_exposed("move_test_status").unwrap().get_description(), "8");
+
+        // 克隆共享数据但不会在销毁时隐藏原变量
+        drop(moved[0].clone());
+        assert!(get_exposed("move_test_status").is_some());
+        assert!(moved[0].hide());
+        assert!(get_exposed("move_test_status").is_none());
+    }
+
+    #[test]
+    fn test_describe_series() {
+        let adder: Adder<i64> = Adder::with_name("series_test_adder").unwrap();
+        let maxer = Maxer::new(0).with_series();
+        let recorder = IntRecorder::new().with_series();
+        let status = Status::new(1.5).with_series();
+        let per_second = PerSecond::new(&adder).with_series();
+        assert!(!adder.describe_series(&mut String::new(), &SeriesOptions::default()));
+
+        // 暴露之后开启，全局表中的变量同样生效
+        adder.enable_series();
+        let series = |var: &dyn Variable| {
+            let mut buf = String::new();
+            assert!(var.describe_series(&mut buf, &SeriesOptions::default()));
+            buf
+        };
+        let exposed = get_exposed("series_test_adder").unwrap();
+        for description in [
+            series(exposed.as_ref()),
+            series(&maxer),
+            series(&recorder),
+            series(&status),
+            series(&per_second),
+        ] {
+            assert!(description.starts_with("{\"meta\":"), "{}", description);
+            assert!(description.contains("\"day\":{\"timestamps\":["), "{}", description);
+        }
+    }
+
+    /// 冲突策略和名称规范化开关是全局的，修改它们的测试需要串行执行
+    static POLICY_LOCK: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn test_to_underscored_name() {
+        let underscored = |src: &str| {
+            let mut name = String::new();
+            to_underscored_name(&mut name, src);
+            name
+        };
+        assert_eq!(underscored("HttpServer.Requests"), "http_server_requests");
+        assert_eq!(underscored("CamelCase"), "camel_case");
+        assert_eq!(underscored("HTTPServer"), "httpserver");
+        assert_eq!(underscored("rpc server--latency 99"), "rpc_server_latency_99");
+        assert_eq!(underscored("already_lower_1"), "already_lower_1");
+        assert_eq!(underscored("_Leading"), "_leading");
+    }
+
+    #[test]
+    fn test_expose_normalized_name() {
+        let _guard = POLICY_LOCK.lock();
+        let status = Status::new(1);
+        assert!(status.expose_as("HttpServer", "Requests.Count").is_ok());
+        assert_eq!(status.name(), "http_server_requests_count");
+        assert!(get_exposed("http_server_requests_count").is_some());
+
+        set_normalize_names(false);
+        let result = status.expose_as("HttpServer", "Requests");
+        set_normalize_names(true);
+        assert!(result.is_ok());
+        assert_eq!(status.name(), "HttpServer_Requests");
+    }
+
+    #[test]
+    fn test_expose_invalid_name() {
+        let _guard = POLICY_LOCK.lock();
+        let status = Status::new(1);
+        assert_eq!(status.expose(""), Err(ExposeError::EmptyName));
+
+        set_normalize_names(false);
+        assert_eq!(status.expose(""), Err(ExposeError::EmptyName));
+        assert_eq!(
+            status.expose_as("bad", "name*"),
+            Err(ExposeError::InvalidCharacters("bad_name*".to_string()))
+        );
+        assert!(status.expose("bad name").is_err());
+        set_normalize_names(true);
+        assert!(status.is_hidden());
+
+        // 规范化之后名称是合法的
+        assert!(status.expose("good name").is_ok());
+        assert_eq!(status.name(), "good_name");
+    }
+
+    #[test]
+    fn test_expose_conflict() {
+        let _guard = POLICY_LOCK.lock();
+        let s1 = Status::new(1);
+        let s2 = Status::new(2);
+        assert!(s1.expose("variable_test_conflict").is_ok());
+        assert_eq!(
+            s2.expose("variable_test_conflict"),
+            Err(ExposeError::NameConflict("variable_test_conflict".to_string()))
+        );
+        assert!(s2.is_hidden());
+        // 用名称创建时同样返回错误
+        assert_eq!(
+            Status::with_name(3, "variable_test_conflict").err(),
+            Some(ExposeError::NameConflict("variable_test_conflict".to_string()))
+        );
+
+        // 隐藏失败的变量不能影响已暴露的变量
+        assert!(!s2.hide());
+        assert_eq!(get_exposed("variable_test_conflict").unwrap().get_description(), "1");
+        assert!(s1.hide());
+    }
+
+    #[test]
+    fn test_conflict_policy() {
+        let _guard = POLICY_LOCK.lock();
+        let s1 = Status::new(1);
+        let s2 = Status::new(2);
+        let s3 = Status::new(3);
+        assert!(s1.expose("variable_test_policy").is_ok());
+
+        set_conflict_policy(ConflictPolicy::AutoSuffix);
+        assert!(s2.expose("variable_test_policy").is_ok());
+        assert!(s3.expose("variable_test_policy").is_ok());
+        assert_eq!(s2.name(), "variable_test_policy_1");
+        assert_eq!(s3.name(), "variable_test_policy_2");
+
+        set_conflict_policy(ConflictPolicy::PanicInDebug);
+        let s4 = Status::new(4);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _ = s4.expose("variable_test_policy");
+        }));
+        set_conflict_policy(ConflictPolicy::Warn);
+        assert_eq!(result.is_err(), cfg!(debug_assertions));
+        assert!(s4.is_hidden());
+    }
+}
@@ -15,22 +15,24 @@
 //! 实现时间窗口统计功能
 
 use std::fmt;
-use std::marker::PhantomData;
-use std::sync::Arc;
-use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
-use parking_lot::RwLock;
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+use num_traits::{NumOps, ToPrimitive};
+use parking_lot::Mutex;
 use std::fmt::Write;
-use std::cell::UnsafeCell;
 
-use crate::variable::Variable;
-
-/// 表示一个时间窗口内的数据样本
-struct Sample<T> {
-    /// 样本数据
-    value: T,
-    /// 采样时间
-    time: Instant,
-}
+use crate::detail::bounded_queue::BoundedQueue;
+use crate::detail::clock::{system_clock, Clock};
+use crate::detail::combiner::{AgentValue, Combiner, InverseOp};
+use crate::detail::sampler::{
+    ReducerSampler, Sample, Sampler, SeriesSampler, SharedSampler, SharedSeries, GLOBAL_SAMPLER_STATE,
+};
+use crate::detail::series::Series;
+use crate::export::{MetricKind, MetricSample};
+use crate::recorder::{Recorder, StatValue};
+use crate::reducer::{AddTo, Adder, ReducerTrait};
+use crate::status::PassiveStatus;
+use crate::variable::{ExposeError, Exposure, SeriesOptions, Variable};
 
 /// 默认的秒级窗口大小 (60秒)
 pub const WINDOW_SIZE_SECOND: u64 = 60;
@@ -50,233 +52,398 @@ pub const SERIES_IN_HOUR: usize = WINDOW_SIZE_HOUR as usize;
 /// 天级序列的最大数据点数量
 pub const SERIES_IN_DAY: usize = WINDOW_SIZE_DAY as usize;
 
-/// 表示一个时间窗口，用于记录和统计时间窗口内的数据
-pub struct Window<T, const N: usize> {
-    /// 数据源
-    source: Arc<dyn Variable>,
-    /// 采样间隔
-    interval: Duration,
-    /// 样本数据
-    samples: RwLock<Vec<Sample<T>>>,
-    /// 变量名称
-    name: UnsafeCell<String>,
-    /// 最近一次采样时间
-    last_sample_time: RwLock<Instant>,
-    /// 标记类型
-    _marker: PhantomData<T>,
+/// 可以被[`Window`]统计的数据源
+pub trait WindowSource: Clone + Send + Sync + 'static {
+    /// 样本的类型
+    type Value: Clone + fmt::Display + Send + Sync + 'static;
+    /// 组合样本的操作
+    type Op: Combiner<Self::Value> + Clone + Send + Sync + 'static;
+    /// `Op`的逆操作，不可逆时为[`VoidOp`]
+    ///
+    /// [`VoidOp`]: crate::reducer::VoidOp
+    type InvOp: InverseOp<Self::Value>;
+
+    /// 获取逆操作
+    fn inv_op(&self) -> Self::InvOp;
+
+    /// 获取数据源上所有窗口共享的采样器
+    fn shared_sampler(&self) -> &SharedSampler;
+
+    /// 描述窗口内的值，默认使用`Value`的`Display`
+    fn describe_value(&self, value: &Self::Value, f: &mut String) {
+        let _ = write!(f, "{}", value);
+    }
+
+    /// 窗口导出到监控系统时的类型
+    fn value_metric_kind(&self) -> MetricKind {
+        MetricKind::Gauge
+    }
+
+    /// 窗口内的值导出的样本，默认把描述解析为一个数值
+    fn value_metric_samples(&self, value: &Self::Value) -> Vec<MetricSample> {
+        let mut description = String::new();
+        self.describe_value(value, &mut description);
+        match description.trim().parse::<f64>() {
+            Ok(value) => vec![MetricSample::new(value)],
+            Err(_) => Vec::new(),
+        }
+    }
 }
 
-// 手动实现线程安全 - 我们确保对UnsafeCell的访问是安全的
-unsafe impl<T, const N: usize> Send for Window<T, N> {}
-unsafe impl<T, const N: usize> Sync for Window<T, N> {}
+/// 数据源使用的采样器
+type SourceSampler<R> = ReducerSampler<
+    R,
+    <R as WindowSource>::Value,
+    <R as WindowSource>::Op,
+    <R as WindowSource>::InvOp,
+>;
 
-impl<T, const N: usize> Window<T, N>
+/// 统计数据源在最近一段时间内的值
+///
+/// 全局采样线程每秒对数据源采样一次。求和这类可逆的操作，窗口内的值为
+/// `最新的累计值 - 窗口起点的累计值`；最大值、最小值这类不可逆的操作，每次采样都会重置
+/// 数据源，窗口内的值为窗口内所有样本的组合，因此数据源的`get_value`只反映最近一秒的值。
+/// 同一个数据源上的多个窗口共享一个采样器。
+///
+/// ```ignore
+/// let latency_max = Maxer::new(0);
+/// let max_in_10s = Window::with_name("latency_max_10s", &latency_max, 10)?;
+/// ```
+#[derive(Clone)]
+pub struct Window<R>
 where
-    T: Clone + fmt::Display + Send + Sync + 'static,
+    R: WindowSource + ReducerTrait<R::Value, R::Op>,
 {
-    /// 创建新的时间窗口
-    pub fn new<S>(source: &S, interval_seconds: u64) -> Self
-    where
-        S: Variable + Clone + 'static,
-    {
+    /// 数据源，用于描述窗口内的值
+    source: R,
+    /// 采样器，由全局采样线程驱动
+    sampler: Arc<SourceSampler<R>>,
+    /// 窗口大小（秒）
+    window_size: u64,
+    /// 暴露信息
+    exposure: Exposure,
+}
+
+impl<R> Window<R>
+where
+    R: WindowSource + ReducerTrait<R::Value, R::Op>,
+{
+    /// 创建统计最近`window_size`秒的窗口
+    pub fn new(source: &R, window_size: u64) -> Self {
+        let window_size = window_size.max(1);
+        let sampler = source.shared_sampler().get_or_create(|| {
+            let sampler = ReducerSampler::new(source, source.op(), source.inv_op());
+            // 先记录一个样本作为起点
+            sampler.take_sample();
+            sampler.schedule();
+            sampler
+        });
+        sampler.set_window_size(window_size as usize);
+
         Self {
-            source: Arc::new(source.clone()),
-            interval: Duration::from_secs(interval_seconds),
-            samples: RwLock::new(Vec::with_capacity(N)),
-            name: UnsafeCell::new(String::new()),
-            last_sample_time: RwLock::new(Instant::now()),
-            _marker: PhantomData,
+            source: source.clone(),
+            sampler,
+            window_size,
+            exposure: Exposure::new(),
         }
     }
-    
-    /// 用名称创建
-    pub fn with_name<S>(name: &str, source: &S, interval_seconds: u64) -> Self
-    where
-        S: Variable + Clone + 'static,
-    {
-        let window = Self::new(source, interval_seconds);
-        let _ = window.expose(name);
-        window
+
+    /// 创建用`clock`计时的窗口，用于测试
+    ///
+    /// 窗口使用独立的采样器，不由全局采样线程驱动，需要调用[`Window::take_sample`]采样
+    pub fn with_clock(source: &R, window_size: u64, clock: Arc<dyn Clock>) -> Self {
+        let window_size = window_size.max(1);
+        let sampler = ReducerSampler::with_clock(source, source.op(), source.inv_op(), clock);
+        sampler.set_window_size(window_size as usize);
+
+        Self {
+            source: source.clone(),
+            sampler,
+            window_size,
+            exposure: Exposure::new(),
+        }
     }
-    
-    /// 获取当前值
-    pub fn get_value(&self) -> Option<T> {
-        // 实现窗口内的数据统计
-        // 这里简单返回最新的样本
-        let samples = self.samples.read();
-        if let Some(sample) = samples.last() {
-            Some(sample.value.clone())
-        } else {
-            None
+
+    /// 用名称创建，名称不可用时返回错误
+    pub fn with_name(name: &str, source: &R, window_size: u64) -> Result<Self, ExposeError> {
+        let window = Self::new(source, window_size);
+        window.expose(name)?;
+        Ok(window)
+    }
+
+    /// 立即对数据源采样一次，同一个数据源上共享采样器的窗口都会看到这个样本
+    pub fn take_sample(&self) {
+        self.sampler.take_sample();
+    }
+
+    /// 获取窗口内的值，还没有样本时返回`None`
+    pub fn get_value(&self) -> Option<R::Value> {
+        self.sampler.get_value(self.window_size as usize)
+    }
+
+    /// 获取窗口内的样本，从旧到新排列
+    pub fn samples(&self) -> Vec<Sample<R::Value>> {
+        self.sampler.samples(self.window_size as usize)
+    }
+
+    /// 获取窗口大小（秒）
+    pub fn window_size(&self) -> u64 {
+        self.window_size
+    }
+}
+
+impl<R> Variable for Window<R>
+where
+    R: WindowSource + ReducerTrait<R::Value, R::Op>,
+{
+    fn describe(&self, f: &mut String, _quote_string: bool) -> bool {
+        match self.get_value() {
+            Some(value) => self.source.describe_value(&value, f),
+            None => f.push_str("N/A"),
         }
+        true
     }
-    
-    /// 添加新的样本
-    fn add_sample(&self, value: T) {
-        let now = Instant::now();
-        let mut samples = self.samples.write();
-        
-        // 添加新样本
-        samples.push(Sample { value, time: now });
-        
-        // 移除过期样本
-        let cutoff = now - self.interval * N as u32;
-        while samples.len() > N || (samples.len() > 0 && samples[0].time < cutoff) {
-            samples.remove(0);
+
+    fn expose_impl(&self, prefix: &str, name: &str) -> Result<(), ExposeError> {
+        self.default_expose_impl(prefix, name)
+    }
+
+    fn exposure(&self) -> Option<&Exposure> {
+        Some(&self.exposure)
+    }
+
+    fn metric_kind(&self) -> MetricKind {
+        self.source.value_metric_kind()
+    }
+
+    fn metric_samples(&self) -> Vec<MetricSample> {
+        match self.get_value() {
+            Some(value) => self.source.value_metric_samples(&value),
+            None => Vec::new(),
         }
-        
-        // 更新最后采样时间
-        *self.last_sample_time.write() = now;
     }
-    
-    /// 触发采样
-    pub fn sample(&self) {
-        // 实际产品中此处需要根据T类型从source获取值
-        // let value = self.source.get_value();
-        // self.add_sample(value);
-        // println!("sample: {}", value);
+}
+
+/// 可以被[`PerSecond`]统计速率的数据源，提供单调累计的值
+pub trait PerSecondSource: Clone + Send + Sync + 'static {
+    /// 读取当前的累计值
+    fn cumulative_value(&self) -> f64;
+}
+
+impl<T> PerSecondSource for Adder<T>
+where
+    T: AgentValue + fmt::Display + NumOps + Default + ToPrimitive,
+{
+    fn cumulative_value(&self) -> f64 {
+        self.get_value().to_f64().unwrap_or(0.0)
     }
 }
 
-impl<T, const N: usize> Variable for Window<T, N>
+impl<T> PerSecondSource for PassiveStatus<T>
 where
-    T: Clone + fmt::Display + Send + Sync + 'static,
+    T: NumOps + Clone + fmt::Display + ToPrimitive + Send + Sync + 'static,
 {
-    fn describe(&self, f: &mut String, quote_string: bool) -> bool {
-        if let Some(value) = self.get_value() {
-            if quote_string && std::any::TypeId::of::<T>() == std::any::TypeId::of::<String>() {
-                let _ = write!(f, "\"{}\"", value);
-            } else {
-                let _ = write!(f, "{}", value);
-            }
+    fn cumulative_value(&self) -> f64 {
+        self.get_value().to_f64().unwrap_or(0.0)
+    }
+}
+
+impl<T: StatValue> PerSecondSource for Recorder<T> {
+    /// 统计每秒记录的样本数
+    fn cumulative_value(&self) -> f64 {
+        self.get_value().num as f64
+    }
+}
+
+/// 每秒从数据源读取一次累计值的采样器
+struct PerSecondSampler<R> {
+    /// 数据源
+    source: R,
+    /// 最近的样本，最多保存窗口大小加一个
+    samples: Mutex<BoundedQueue<Sample<f64>>>,
+    /// 采样时间使用的时钟
+    clock: Arc<dyn Clock>,
+}
+
+impl<R: PerSecondSource> PerSecondSampler<R> {
+    /// 以`now`为采样时间记录一个样本
+    fn take_sample_at(&self, now: Instant) {
+        let value = self.source.cumulative_value();
+        self.samples.lock().elim_push(Sample { value, time: now });
+    }
+
+    /// 计算最近`window_size`秒内的平均速率，样本不足两个时返回0
+    fn rate(&self, window_size: u64) -> f64 {
+        let samples = self.samples.lock();
+        if samples.len() < 2 {
+            return 0.0;
+        }
+        let index = (window_size as usize).min(samples.len() - 1);
+        let (Some(latest), Some(oldest)) = (samples.top(0), samples.top(index)) else {
+            return 0.0;
+        };
+        let elapsed = latest.time.duration_since(oldest.time).as_secs_f64();
+        if elapsed > 0.0 {
+            (latest.value - oldest.value) / elapsed
         } else {
-            let _ = write!(f, "N/A");
+            0.0
         }
-        true
     }
-    
-    fn expose_impl(&self, prefix: &str, name: &str) -> i32 {
-        // 更新内部名称
-        let mut full_name = String::new();
-        if !prefix.is_empty() {
-            full_name.push_str(prefix);
-            full_name.push('_');
-        }
-        full_name.push_str(name);
-        
-        // 将自己暴露出去
-        let result = <Window<T, N> as Variable>::default_expose_impl(self, prefix, name);
-        if result == 0 {
-            // 仅在成功时更新名称
-            // 使用UnsafeCell安全地更新内部状态
-            unsafe {
-                *self.name.get() = full_name;
+}
+
+impl<R: PerSecondSource> Sampler for PerSecondSampler<R> {
+    fn interval(&self) -> Duration {
+        Duration::from_secs(1)
+    }
+
+    fn take_sample(&self) {
+        self.take_sample_at(self.clock.now());
+    }
+
+    fn describe(&self, f: &mut dyn fmt::Write) {
+        let samples = self.samples.lock();
+        let _ = write!(f, "[");
+        for (i, sample) in samples.iter().enumerate() {
+            if i > 0 {
+                let _ = write!(f, ",");
             }
+            let _ = write!(f, "{}", sample.value);
         }
-        result
+        let _ = write!(f, "]");
     }
-    
-    fn name(&self) -> String {
-        unsafe { (*self.name.get()).clone() }
+
+    fn destroy(&self) {
+        self.samples.lock().clear();
     }
 }
 
-/// 表示单位时间内的操作次数
-pub struct PerSecond<T> {
-    /// 内部窗口
-    window: Window<f64, SERIES_IN_SECOND>,
-    /// 上次统计的值
-    last_value: RwLock<Option<T>>,
-    /// 上次统计的时间
-    last_time: RwLock<Instant>,
-    /// 变量名称
-    name: UnsafeCell<String>,
+/// 统计数据源在最近一段时间内每秒的增量，如QPS
+///
+/// 全局采样线程每秒读取一次数据源的累计值，速率为
+/// `(最新的值 - 窗口起点的值) / 经过的秒数`，窗口默认为60秒：
+///
+/// ```ignore
+/// let requests: Adder<i64> = Adder::new();
+/// let qps = PerSecond::with_name("requests_qps", &requests)?;
+/// ```
+#[derive(Clone)]
+pub struct PerSecond<R> {
+    /// 采样器，由全局采样线程驱动
+    sampler: Arc<PerSecondSampler<R>>,
+    /// 窗口大小（秒）
+    window_size: u64,
+    /// 历史数据，默认不开启
+    series: SharedSeries,
+    /// 暴露信息
+    exposure: Exposure,
 }
 
-// 手动实现线程安全 - 我们确保对UnsafeCell的访问是安全的
-unsafe impl<T> Send for PerSecond<T> {}
-unsafe impl<T> Sync for PerSecond<T> {}
+impl<R: PerSecondSource> PerSecond<R> {
+    /// 创建统计最近60秒的速率统计器
+    pub fn new(source: &R) -> Self {
+        Self::with_window_size(source, WINDOW_SIZE_SECOND)
+    }
+
+    /// 创建统计最近`window_size`秒的速率统计器
+    pub fn with_window_size(source: &R, window_size: u64) -> Self {
+        let per_second = Self::with_clock(source, window_size, system_clock());
+        let weak: Weak<dyn Sampler> =
+            Arc::downgrade(&per_second.sampler) as Weak<PerSecondSampler<R>>;
+        GLOBAL_SAMPLER_STATE.lock().register_sampler(weak);
+        per_second
+    }
+
+    /// 创建用`clock`计时的速率统计器，用于测试
+    ///
+    /// 不由全局采样线程驱动，需要调用[`PerSecond::take_sample`]采样
+    pub fn with_clock(source: &R, window_size: u64, clock: Arc<dyn Clock>) -> Self {
+        let window_size = window_size.max(1);
+        let sampler = Arc::new(PerSecondSampler {
+            source: source.clone(),
+            samples: Mutex::new(BoundedQueue::new(window_size as usize + 1)),
+            clock,
+        });
+        // 先记录一个样本作为起点
+        sampler.take_sample();
 
-impl<T> PerSecond<T>
-where
-    T: Clone + fmt::Display + Send + Sync + 'static,
-{
-    /// 创建新的QPS统计器
-    pub fn new<S>(source: &S) -> Self
-    where
-        S: Variable + Clone + 'static,
-    {
         Self {
-            window: Window::new(source, 1),
-            last_value: RwLock::new(None),
-            last_time: RwLock::new(Instant::now()),
-            name: UnsafeCell::new(String::new()),
+            sampler,
+            window_size,
+            series: SharedSeries::new(),
+            exposure: Exposure::new(),
         }
     }
-    
-    /// 用名称创建
-    pub fn with_name<S>(name: &str, source: &S) -> Self
-    where
-        S: Variable + Clone + 'static,
-    {
+
+    /// 立即读取一次数据源的累计值
+    pub fn take_sample(&self) {
+        self.sampler.take_sample();
+    }
+
+    /// 用名称创建，名称不可用时返回错误
+    pub fn with_name(name: &str, source: &R) -> Result<Self, ExposeError> {
         let per_second = Self::new(source);
-        let _ = per_second.expose(name);
-        per_second
+        per_second.expose(name)?;
+        Ok(per_second)
     }
-    
-    /// 获取当前QPS
+
+    /// 获取默认窗口内的每秒速率
     pub fn get_value(&self) -> f64 {
-        // 实际产品中需要根据T类型计算QPS
-        // 这里简单返回窗口中的平均值
-        self.window.get_value().unwrap_or(0.0)
+        self.sampler.rate(self.window_size)
+    }
+
+    /// 获取最近`window_size`秒内的每秒速率，不能超过创建时的窗口大小
+    pub fn get_value_in(&self, window_size: u64) -> f64 {
+        self.sampler.rate(window_size.clamp(1, self.window_size))
+    }
+
+    /// 获取窗口大小（秒）
+    pub fn window_size(&self) -> u64 {
+        self.window_size
+    }
+
+    /// 开启历史数据
+    pub fn with_series(self) -> Self {
+        self.enable_series();
+        self
+    }
+
+    /// 开启历史数据，每秒记录一次速率，合并为更粗的粒度时取平均值
+    ///
+    /// 对所有克隆以及已经暴露的变量生效
+    pub fn enable_series(&self) {
+        let sampler = self.sampler.clone();
+        let window_size = self.window_size;
+        self.series.enable(|| {
+            SeriesSampler::new(Series::averaged(AddTo::<f64>::default()), move || {
+                sampler.rate(window_size)
+            })
+        });
     }
 }
 
-impl<T> Variable for PerSecond<T>
-where
-    T: Clone + fmt::Display + Send + Sync + 'static,
-{
+impl<R: PerSecondSource> Variable for PerSecond<R> {
     fn describe(&self, f: &mut String, _quote_string: bool) -> bool {
-        let _ = write!(f, "{}", self.get_value());
+        // 保留三位小数，避免输出过长的浮点数
+        let _ = write!(f, "{}", (self.get_value() * 1000.0).round() / 1000.0);
         true
     }
-    
-    fn expose_impl(&self, prefix: &str, name: &str) -> i32 {
-        // 更新内部名称
-        let mut full_name = String::new();
-        if !prefix.is_empty() {
-            full_name.push_str(prefix);
-            full_name.push('_');
-        }
-        full_name.push_str(name);
-        
-        // 将自己暴露出去
-        let result = <PerSecond<T> as Variable>::default_expose_impl(self, prefix, name);
-        if result == 0 {
-            // 仅在成功时更新名称
-            // 使用UnsafeCell安全地更新内部状态
-            unsafe {
-                *self.name.get() = full_name;
-            }
-            
-            // 同时暴露内部窗口
-            let window_name = format!("{}_second", name);
-            let _ = self.window.expose_as(prefix, &window_name);
-        }
-        result
+
+    fn expose_impl(&self, prefix: &str, name: &str) -> Result<(), ExposeError> {
+        self.default_expose_impl(prefix, name)
     }
-    
-    fn name(&self) -> String {
-        unsafe { (*self.name.get()).clone() }
+
+    fn exposure(&self) -> Option<&Exposure> {
+        Some(&self.exposure)
+    }
+
+    fn describe_series(&self, f: &mut dyn fmt::Write, options: &SeriesOptions) -> bool {
+        self.series.describe(self, f, options)
     }
 }
 
 /// 返回当前的Unix时间戳（毫秒）
 pub fn current_time_ms() -> u64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_else(|_| Duration::from_secs(0))
-        .as_millis() as u64
+    system_clock().now_ms()
 }
 
 /// 时间窗口定义
@@ -390,6 +557,9 @@ impl CommonWindows {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::detail::clock::MockClock;
+    use crate::recorder::{IntRecorder, RecorderDisplay};
+    use crate::reducer::Maxer;
     use std::thread::sleep;
     
     #[test]
@@ -442,6 +612,89 @@ mod tests {
         assert_eq!(all_windows.len(), 10);
     }
     
+    /// 模拟采样线程每秒一次的调用
+    fn tick_every_second(clock: &MockClock, mut take_sample: impl FnMut()) {
+        take_sample();
+        clock.advance(Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_window() {
+        let clock = Arc::new(MockClock::new());
+
+        let adder: Adder<i64> = Adder::new();
+        let sum_in_2s = Window::with_clock(&adder, 2, clock.clone());
+        assert_eq!(sum_in_2s.get_value(), None);
+        for second in 0..4 {
+            adder.add(second + 1);
+            tick_every_second(&clock, || sum_in_2s.take_sample());
+        }
+        // 累计值依次为1、3、6、10
+        assert_eq!(sum_in_2s.get_value(), Some(7));
+        let samples = sum_in_2s.samples();
+        assert_eq!(samples.len(), 3);
+        assert_eq!(samples[2].time - samples[0].time, Duration::from_secs(2));
+        let mut description = String::new();
+        sum_in_2s.describe(&mut description, false);
+        assert_eq!(description, "7");
+
+        let maxer = Maxer::new(0);
+        let max_in_2s = Window::with_clock(&maxer, 2, clock.clone());
+        for value in [8, 3, 5] {
+            maxer.add(value);
+            tick_every_second(&clock, || max_in_2s.take_sample());
+        }
+        assert_eq!(max_in_2s.get_value(), Some(5));
+
+        let recorder = IntRecorder::new();
+        let average_in_2s = Window::with_clock(&recorder, 2, clock.clone());
+        for value in [100, 10, 20] {
+            recorder.add(value);
+            tick_every_second(&clock, || average_in_2s.take_sample());
+        }
+        assert_eq!(average_in_2s.get_value().unwrap().get_average_int(), 15);
+        // 窗口按数据源的输出方式描述
+        assert_eq!(average_in_2s.get_description(), "15");
+        recorder.set_display(RecorderDisplay::FloatAverage(1));
+        assert_eq!(average_in_2s.get_description(), "15.0");
+        assert_eq!(average_in_2s.metric_samples()[0], MetricSample::new(30.0).with_suffix("_sum"));
+
+        // 同一个数据源上的窗口共享采样器
+        let max_in_10s = Window::new(&maxer, 10);
+        let max_in_60s = Window::new(&maxer.clone(), 60);
+        assert!(Arc::ptr_eq(&max_in_10s.sampler, &max_in_60s.sampler));
+    }
+
+    #[test]
+    fn test_per_second() {
+        let clock = Arc::new(MockClock::new());
+        let adder: Adder<i64> = Adder::new();
+        let qps = PerSecond::with_clock(&adder, 3, clock.clone());
+        assert_eq!(qps.get_value(), 0.0);
+
+        for value in [0, 10, 30, 60, 100, 150] {
+            adder.add(value - adder.get_value());
+            tick_every_second(&clock, || qps.take_sample());
+        }
+
+        // 只保留最近3秒的4个样本：30、60、100、150
+        assert_eq!(qps.get_value(), (150.0 - 30.0) / 3.0);
+        assert_eq!(qps.get_value_in(1), 50.0);
+        assert_eq!(qps.get_value_in(100), 40.0);
+        let mut description = String::new();
+        qps.describe(&mut description, false);
+        assert_eq!(description, "40");
+
+        let recorder = IntRecorder::new();
+        let samples_per_second = PerSecond::with_clock(&recorder, 10, clock.clone());
+        for _ in 0..20 {
+            recorder.add(7);
+        }
+        clock.advance(Duration::from_secs(4));
+        samples_per_second.take_sample();
+        assert_eq!(samples_per_second.get_value(), 5.0);
+    }
+
     #[test]
     fn test_current_time_ms() {
         let t1 = current_time_ms();